@@ -1,9 +1,11 @@
 import { ref } from 'vue'
 import ChatService from '@/shared/services/chat.service'
+import { STORAGE_KEYS } from '@/shared/keys'
 
 export function useWebSocket(url, chatId) {
   const messages = ref([])
-  const socket = new WebSocket(url)
+  const token = localStorage.getItem(STORAGE_KEYS.ACCESS_TOKEN)
+  const socket = new WebSocket(`${url}?token=${encodeURIComponent(token)}`)
 
   const getChatMessages = async () => {
     try {
@@ -0,0 +1,87 @@
+//! Measures fan-out through the connection registry alone.
+//!
+//! `cargo run --release --example bench_hub -- [connections]`
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use server::websockets::hub::Hub;
+
+const DEFAULT_CONNECTIONS: usize = 10_000;
+const DEVICES_PER_USER: usize = 2;
+const CHAT_MEMBERS: usize = 50;
+const BROADCASTS_PER_SENDER: usize = 20_000;
+
+/// Members of a simulated chat, spread over all users so that chats overlap like real ones do.
+fn chat_members(chat: usize, users: usize) -> Vec<i32> {
+    (0..CHAT_MEMBERS)
+        .map(|member| ((chat * 7919 + member * 104_729) % users) as i32)
+        .collect()
+}
+
+/// Registers `connections` sockets, `DEVICES_PER_USER` per user, and broadcasts to chats of
+/// `CHAT_MEMBERS` users from one task per CPU at once. No database or network is involved,
+/// so the numbers are an upper bound for the hub alone.
+async fn run(connections: usize) {
+    let hub = Arc::new(Hub::new());
+    let users = (connections / DEVICES_PER_USER).max(1);
+    let delivered = Arc::new(AtomicU64::new(0));
+    let mut readers = Vec::with_capacity(connections);
+
+    for connection in 0..connections {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+        hub.register((connection % users) as i32, sender);
+
+        let delivered = delivered.clone();
+        readers.push(tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                delivered.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+    }
+
+    let senders = std::thread::available_parallelism().map(|cpus| cpus.get()).unwrap_or(4);
+    let message = Message::Text(r#"{"type":"new_message","chat_id":1,"content":"Hello"}"#.to_string());
+    let started = Instant::now();
+
+    let broadcasters: Vec<_> = (0..senders)
+        .map(|sender| {
+            let hub = hub.clone();
+            let message = message.clone();
+
+            tokio::spawn(async move {
+                for broadcast in 0..BROADCASTS_PER_SENDER {
+                    hub.send_to_users(&chat_members(sender * BROADCASTS_PER_SENDER + broadcast, users), &message);
+                }
+            })
+        })
+        .collect();
+
+    for broadcaster in broadcasters {
+        broadcaster.await.expect("Broadcast task panicked");
+    }
+    let broadcast_time = started.elapsed();
+
+    // Без хаба закриваються всі канали, і читачі завершуються, щойно все прочитали
+    drop(hub);
+    for reader in readers {
+        reader.await.expect("Reader task panicked");
+    }
+    let total_time = started.elapsed();
+
+    let broadcasts = (senders * BROADCASTS_PER_SENDER) as f64;
+    let delivered = delivered.load(Ordering::Relaxed) as f64;
+
+    println!("connections:       {} ({} users, {} devices each)", connections, users, DEVICES_PER_USER);
+    println!("broadcasts:        {} to {} members from {} tasks", broadcasts, CHAT_MEMBERS, senders);
+    println!("broadcasts/s:      {:.0}", broadcasts / broadcast_time.as_secs_f64());
+    println!("deliveries/s:      {:.0} (until every socket read its frames)", delivered / total_time.as_secs_f64());
+}
+
+#[rocket::main]
+async fn main() {
+    let connections = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_CONNECTIONS);
+    run(connections).await;
+}
@@ -0,0 +1,175 @@
+//! Loads the message write pipeline against the configured database.
+//!
+//! `cargo run --release --example bench_writer -- [messages]`
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use dotenv::dotenv;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use server::config::{Config, PersistenceConfig};
+use server::services::health::MIGRATOR;
+use server::services::message_writer::{MessageWriter, NewMessage};
+use server::telemetry;
+
+const DEFAULT_MESSAGES: usize = 100_000;
+const SENDERS: usize = 500;
+const CHATS: usize = 100;
+
+/// Creates throwaway users and direct chats for the run. Deleting the users removes the rest.
+async fn create_chats(pool: &PgPool) -> Result<(Vec<i32>, Vec<i32>), sqlx::Error> {
+    let run = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis().to_string();
+
+    // Пароль '!' не є хешем bcrypt, тож під цими користувачами не увійти
+    let user_ids = sqlx::query_scalar!(
+        r#"
+        INSERT INTO users (email, password)
+        SELECT 'bench-writer-' || $1 || '-' || n || '@example.invalid', '!'
+        FROM generate_series(1, $2) AS n
+        RETURNING id
+        "#,
+        run,
+        (CHATS * 2) as i32
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut chat_ids = Vec::with_capacity(CHATS);
+
+    for pair in user_ids.chunks(2) {
+        let chat_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO chats (user1_id, user2_id, user1_email, user2_email)
+            SELECT user1.id, user2.id, user1.email, user2.email
+            FROM users user1, users user2
+            WHERE user1.id = $1 AND user2.id = $2
+            RETURNING id
+            "#,
+            pair[0],
+            pair[1]
+        )
+            .fetch_one(pool)
+            .await?;
+
+        chat_ids.push(chat_id);
+    }
+
+    Ok((user_ids, chat_ids))
+}
+
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    sorted.get((sorted.len() * percent / 100).min(sorted.len().saturating_sub(1))).copied().unwrap_or_default()
+}
+
+/// Sends `messages` messages through the write pipeline from `SENDERS` tasks, each waiting for
+/// its previous message to be committed like a connection does, spread over `CHATS` chats.
+/// Reports throughput, commit latency and whether every chat kept its order.
+async fn run(pool: &PgPool, config: &PersistenceConfig, messages: usize) {
+    let (user_ids, chat_ids) = create_chats(pool).await.expect("Failed to create benchmark chats");
+    let writer = MessageWriter::start(pool.clone(), config);
+
+    // Останній збережений id кожного чату: розсилка в іншому порядку означала б порушення черговості
+    let last_ids = Arc::new(Mutex::new(HashMap::<i32, i32>::new()));
+    let out_of_order = Arc::new(Mutex::new(0usize));
+    let chat_ids = Arc::new(chat_ids);
+    let user_ids = Arc::new(user_ids);
+
+    let started = Instant::now();
+
+    let senders: Vec<_> = (0..SENDERS)
+        .map(|sender| {
+            let writer = writer.clone();
+            let chat_ids = chat_ids.clone();
+            let user_ids = user_ids.clone();
+            let last_ids = last_ids.clone();
+            let out_of_order = out_of_order.clone();
+
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let chat = sender % CHATS;
+
+                for n in (sender..messages).step_by(SENDERS) {
+                    let chat_id = chat_ids[chat];
+                    let message = NewMessage {
+                        chat_id,
+                        user_id: user_ids[chat * 2 + n % 2],
+                        content: format!("Benchmark message {}", n),
+                        file_path: None,
+                        file_uploaded: false,
+                        message_type: "text".to_string(),
+                        reply_to_message_id: None,
+                        thread_root_id: None,
+                    };
+
+                    let last_ids = last_ids.clone();
+                    let out_of_order = out_of_order.clone();
+                    let on_commit = Box::new(move |message_id: i32| {
+                        let previous = last_ids.lock().unwrap().insert(chat_id, message_id);
+
+                        if previous.is_some_and(|previous| previous > message_id) {
+                            *out_of_order.lock().unwrap() += 1;
+                        }
+                    });
+
+                    let sent = Instant::now();
+                    match writer.save(message, on_commit).await {
+                        Ok(_) => latencies.push(sent.elapsed()),
+                        Err(e) => tracing::error!(error = %e, "Benchmark message was not stored"),
+                    }
+                }
+
+                latencies
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(messages);
+    for sender in senders {
+        latencies.extend(sender.await.expect("Sender task panicked"));
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable();
+
+    if let Err(e) = sqlx::query!("DELETE FROM users WHERE id = ANY($1)", &user_ids[..]).execute(pool).await {
+        tracing::error!(error = %e, "Failed to remove benchmark users");
+    }
+
+    println!("messages stored:   {} of {} in {} chats from {} senders", latencies.len(), messages, CHATS, SENDERS);
+    println!("writers:           {}, batches of up to {}", config.writers, config.batch_size);
+    println!("messages/s:        {:.0}", latencies.len() as f64 / elapsed.as_secs_f64());
+    println!("commit latency:    p50 {:?}, p95 {:?}, p99 {:?}", percentile(&latencies, 50), percentile(&latencies, 95), percentile(&latencies, 99));
+    println!("out of order:      {}", out_of_order.lock().unwrap());
+}
+
+#[rocket::main]
+async fn main() {
+    dotenv().ok();
+
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+
+    if let Err(error) = telemetry::init(&config.logging) {
+        eprintln!("Failed to set up logging: {}", error);
+        std::process::exit(1);
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .acquire_timeout(Duration::from_secs(config.database.acquire_timeout_seconds))
+        .connect(config.database.url.expose())
+        .await
+        .expect("Failed to connect to the database");
+
+    MIGRATOR
+        .run(&pool)
+        .await
+        .expect("Failed to apply database migrations");
+
+    let messages = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_MESSAGES);
+    run(&pool, &config.persistence, messages).await;
+
+    telemetry::shutdown().await;
+}
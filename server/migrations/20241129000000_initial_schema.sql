@@ -0,0 +1,36 @@
+-- The schema the first version of the server ran against: users, direct chats between two users
+-- and their messages. Everything else is added by the later migrations.
+-- Tables and indexes are only created if missing, so databases set up by hand can adopt the
+-- migration history without being recreated.
+CREATE TABLE IF NOT EXISTS users (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR(255) NOT NULL UNIQUE,
+    password VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS chats (
+    id SERIAL PRIMARY KEY,
+    user1_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    user2_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    user1_email VARCHAR(255) NOT NULL,
+    user2_email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+    CONSTRAINT chats_distinct_users_check CHECK (user1_id <> user2_id)
+);
+
+-- Two users share at most one direct chat, whichever of them started it.
+CREATE UNIQUE INDEX IF NOT EXISTS chats_user_pair_idx ON chats (LEAST(user1_id, user2_id), GREATEST(user1_id, user2_id));
+
+CREATE TABLE IF NOT EXISTS messages (
+    id SERIAL PRIMARY KEY,
+    chat_id INTEGER NOT NULL REFERENCES chats (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+    file_path VARCHAR(255),
+    message_type VARCHAR(20) DEFAULT 'text'
+);
+
+-- messages (chat_id, id) and (chat_id, created_at) are indexed by the read receipts and search migrations.
+CREATE INDEX IF NOT EXISTS messages_user_id_idx ON messages (user_id);
@@ -0,0 +1,4 @@
+-- Presence: last time a user had an open socket and whether they hide it from others.
+ALTER TABLE users
+    ADD COLUMN last_seen_at TIMESTAMP,
+    ADD COLUMN hide_presence BOOLEAN NOT NULL DEFAULT FALSE;
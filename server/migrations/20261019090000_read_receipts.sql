@@ -0,0 +1,25 @@
+-- Per-member state of a chat. Read and delivery cursors hold the id of the last message
+-- the member has read / received; message status is derived from them.
+CREATE TABLE chat_members (
+    chat_id INTEGER NOT NULL REFERENCES chats (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    last_read_message_id INTEGER,
+    last_delivered_message_id INTEGER,
+    joined_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE INDEX chat_members_user_id_idx ON chat_members (user_id);
+CREATE INDEX IF NOT EXISTS messages_chat_id_id_idx ON messages (chat_id, id);
+
+INSERT INTO chat_members (chat_id, user_id)
+SELECT id, user1_id FROM chats
+UNION
+SELECT id, user2_id FROM chats;
+
+-- Existing history is treated as read.
+UPDATE chat_members cm
+SET last_read_message_id = latest.message_id,
+    last_delivered_message_id = latest.message_id
+FROM (SELECT chat_id, MAX(id) AS message_id FROM messages GROUP BY chat_id) latest
+WHERE latest.chat_id = cm.chat_id;
@@ -0,0 +1,23 @@
+-- Denormalized pointer to the newest message so the chat list can be ordered by activity
+-- without scanning messages.
+ALTER TABLE chats
+    ADD COLUMN last_message_id INTEGER REFERENCES messages (id) ON DELETE SET NULL,
+    ADD COLUMN last_activity_at TIMESTAMP;
+
+UPDATE chats c
+SET last_message_id = latest.id,
+    last_activity_at = latest.created_at
+FROM (
+    SELECT DISTINCT ON (chat_id) chat_id, id, created_at
+    FROM messages
+    ORDER BY chat_id, id DESC
+) latest
+WHERE latest.chat_id = c.id;
+
+UPDATE chats SET last_activity_at = COALESCE(created_at, NOW()) WHERE last_activity_at IS NULL;
+
+ALTER TABLE chats
+    ALTER COLUMN last_activity_at SET DEFAULT NOW(),
+    ALTER COLUMN last_activity_at SET NOT NULL;
+
+CREATE INDEX chats_last_activity_at_idx ON chats (last_activity_at DESC);
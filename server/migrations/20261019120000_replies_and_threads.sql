@@ -0,0 +1,9 @@
+-- A reply quotes an earlier message of the same chat. A thread groups replies under a
+-- root message; the root keeps a counter so the chat list doesn't have to count them.
+ALTER TABLE messages
+    ADD COLUMN reply_to_message_id INTEGER REFERENCES messages (id) ON DELETE SET NULL,
+    ADD COLUMN thread_root_id INTEGER REFERENCES messages (id) ON DELETE CASCADE,
+    ADD COLUMN reply_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN last_reply_at TIMESTAMP;
+
+CREATE INDEX messages_thread_root_id_idx ON messages (thread_root_id, id) WHERE thread_root_id IS NOT NULL;
@@ -0,0 +1,46 @@
+-- Full-text search over message text and attachment file names.
+-- The text search configuration (stemming language) lives in the database so the trigger
+-- and the queries agree on it; the server updates it from SEARCH_CONFIG at startup.
+CREATE TABLE search_settings (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    config REGCONFIG NOT NULL DEFAULT 'english'
+);
+
+INSERT INTO search_settings DEFAULT VALUES;
+
+-- Uploaded files are stored as uploads/chat_<chat id>_<uuid>_<original name>.
+CREATE FUNCTION message_search_vector(content TEXT, file_path TEXT) RETURNS TSVECTOR
+LANGUAGE sql STABLE AS $$
+    SELECT setweight(to_tsvector(s.config, COALESCE(content, '')), 'A')
+        || setweight(
+            to_tsvector(
+                s.config,
+                translate(
+                    regexp_replace(regexp_replace(COALESCE(file_path, ''), '^.*/', ''), '^chat_\d+_[0-9a-fA-F-]{36}_', ''),
+                    '._-',
+                    '   '
+                )
+            ),
+            'B'
+        )
+    FROM search_settings s
+$$;
+
+ALTER TABLE messages ADD COLUMN search_vector TSVECTOR;
+
+UPDATE messages SET search_vector = message_search_vector(content, file_path);
+
+CREATE FUNCTION messages_search_vector_trigger() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.search_vector := message_search_vector(NEW.content, NEW.file_path);
+    RETURN NEW;
+END
+$$;
+
+CREATE TRIGGER messages_search_vector_update
+    BEFORE INSERT OR UPDATE OF content, file_path ON messages
+    FOR EACH ROW EXECUTE FUNCTION messages_search_vector_trigger();
+
+CREATE INDEX messages_search_vector_idx ON messages USING GIN (search_vector);
+CREATE INDEX messages_chat_id_created_at_idx ON messages (chat_id, created_at);
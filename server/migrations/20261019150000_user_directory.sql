@@ -0,0 +1,9 @@
+-- User directory: prefix and fuzzy (trigram) search by email and display name.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+ALTER TABLE users
+    ADD COLUMN display_name VARCHAR(64),
+    ADD COLUMN discoverable BOOLEAN NOT NULL DEFAULT TRUE;
+
+CREATE INDEX users_email_trgm_idx ON users USING GIN (lower(email) gin_trgm_ops);
+CREATE INDEX users_display_name_trgm_idx ON users USING GIN (lower(display_name) gin_trgm_ops);
@@ -0,0 +1,16 @@
+-- Profile fields. display_name was added together with the user directory.
+ALTER TABLE users
+    ADD COLUMN avatar_path VARCHAR(255),
+    ADD COLUMN bio VARCHAR(500),
+    ADD COLUMN status_text VARCHAR(100),
+    ADD COLUMN status_expires_at TIMESTAMP;
+
+-- What other users get to see about someone wherever they appear (chat list, messages, ...).
+-- Without a display name the local part of the email is shown; expired statuses are hidden.
+CREATE VIEW user_summaries AS
+SELECT
+    id,
+    COALESCE(NULLIF(display_name, ''), split_part(email, '@', 1)) AS display_name,
+    avatar_path,
+    CASE WHEN status_expires_at IS NULL OR status_expires_at > NOW() THEN status_text END AS status_text
+FROM users;
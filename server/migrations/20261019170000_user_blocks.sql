@@ -0,0 +1,16 @@
+-- Blocking is one-directional: the blocker stops receiving anything from the blocked user.
+CREATE TABLE user_blocks (
+    blocker_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    blocked_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blocker_id, blocked_id),
+    CHECK (blocker_id <> blocked_id)
+);
+
+CREATE INDEX user_blocks_blocked_idx ON user_blocks (blocked_id);
+
+-- Who may open a direct chat with the user.
+CREATE TYPE direct_chat_policy AS ENUM ('everyone', 'contacts', 'nobody');
+
+ALTER TABLE users
+    ADD COLUMN direct_chat_policy direct_chat_policy NOT NULL DEFAULT 'everyone';
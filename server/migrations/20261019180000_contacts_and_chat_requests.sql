@@ -0,0 +1,17 @@
+-- Personal contact lists. Adding someone does not add you to their list.
+CREATE TABLE contacts (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    contact_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, contact_id),
+    CHECK (user_id <> contact_id)
+);
+
+-- A chat started by a non-contact is a request until the recipient accepts it.
+-- Existing chats count as accepted.
+CREATE TYPE chat_request_status AS ENUM ('pending', 'accepted', 'declined');
+
+ALTER TABLE chat_members
+    ADD COLUMN request_status chat_request_status NOT NULL DEFAULT 'accepted';
+
+CREATE INDEX chat_members_user_status_idx ON chat_members (user_id, request_status);
@@ -0,0 +1,10 @@
+-- Per-member chat settings. A mute without muted_until lasts until it is lifted;
+-- pinned chats are ordered by pinned_position, NULL means not pinned.
+ALTER TABLE chat_members
+    ADD COLUMN muted BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN muted_until TIMESTAMP,
+    ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN pinned_position INTEGER,
+    ADD COLUMN favourite BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX chat_members_pinned_idx ON chat_members (user_id, pinned_position) WHERE pinned_position IS NOT NULL;
@@ -0,0 +1,71 @@
+-- Unread messages of a chat for one of its members, by the same rules as the chat list:
+-- the member's own, deleted and blocked users' messages don't count.
+CREATE FUNCTION unread_message_count(p_chat_id INTEGER, p_user_id INTEGER) RETURNS BIGINT
+LANGUAGE sql STABLE AS $$
+    SELECT COUNT(*)
+    FROM chat_members cm
+    JOIN messages m ON m.chat_id = cm.chat_id
+    WHERE cm.chat_id = p_chat_id
+      AND cm.user_id = p_user_id
+      AND m.id > COALESCE(cm.last_read_message_id, 0)
+      AND m.user_id <> p_user_id
+      AND m.deleted_at IS NULL
+      AND NOT EXISTS (
+          SELECT 1 FROM user_blocks b
+          WHERE b.blocker_id = p_user_id AND b.blocked_id = m.user_id AND b.created_at <= m.created_at
+      )
+$$;
+
+-- User-defined chat lists. A chat is in a folder if it was added manually or matches
+-- any of the inclusion rules, unless the folder excludes muted chats and it is muted.
+CREATE TABLE chat_folders (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    position INTEGER NOT NULL,
+    include_unread BOOLEAN NOT NULL DEFAULT FALSE,
+    include_groups BOOLEAN NOT NULL DEFAULT FALSE,
+    include_direct BOOLEAN NOT NULL DEFAULT FALSE,
+    exclude_muted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX chat_folders_user_idx ON chat_folders (user_id, position);
+
+CREATE TABLE chat_folder_chats (
+    folder_id INTEGER NOT NULL REFERENCES chat_folders(id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    PRIMARY KEY (folder_id, chat_id)
+);
+
+-- Every chat with one of these users is included.
+CREATE TABLE chat_folder_contacts (
+    folder_id INTEGER NOT NULL REFERENCES chat_folders(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (folder_id, user_id)
+);
+
+CREATE FUNCTION chat_in_folder(p_folder_id INTEGER, p_chat_id INTEGER) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT EXISTS (
+        SELECT 1
+        FROM chat_folders f
+        JOIN chat_members cm ON cm.chat_id = p_chat_id AND cm.user_id = f.user_id
+        CROSS JOIN LATERAL (SELECT COUNT(*) AS count FROM chat_members g WHERE g.chat_id = p_chat_id) members
+        WHERE f.id = p_folder_id
+          AND cm.request_status = 'accepted'
+          AND NOT (f.exclude_muted AND cm.muted AND (cm.muted_until IS NULL OR cm.muted_until > NOW()))
+          AND (
+              EXISTS (SELECT 1 FROM chat_folder_chats fc WHERE fc.folder_id = f.id AND fc.chat_id = p_chat_id)
+              OR (f.include_unread AND unread_message_count(p_chat_id, f.user_id) > 0)
+              OR (f.include_groups AND members.count > 2)
+              OR (f.include_direct AND members.count = 2)
+              OR EXISTS (
+                  SELECT 1
+                  FROM chat_folder_contacts fu
+                  JOIN chat_members other ON other.chat_id = p_chat_id AND other.user_id = fu.user_id
+                  WHERE fu.folder_id = f.id
+              )
+          )
+    )
+$$;
@@ -0,0 +1,56 @@
+-- Group chats: a title and avatar instead of a fixed pair of users.
+ALTER TABLE chats
+    ADD COLUMN is_group BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN title VARCHAR(128),
+    ADD COLUMN avatar_path VARCHAR(255),
+    ALTER COLUMN user1_id DROP NOT NULL,
+    ALTER COLUMN user2_id DROP NOT NULL,
+    ALTER COLUMN user1_email DROP NOT NULL,
+    ALTER COLUMN user2_email DROP NOT NULL,
+    ADD CONSTRAINT chats_kind_check CHECK (
+        (is_group AND title IS NOT NULL)
+        OR (NOT is_group AND user1_id IS NOT NULL AND user2_id IS NOT NULL)
+    );
+
+-- Member roles. Members of direct chats are plain members; every group has exactly one owner.
+CREATE TYPE chat_role AS ENUM ('owner', 'admin', 'member', 'read_only');
+
+ALTER TABLE chat_members
+    ADD COLUMN role chat_role NOT NULL DEFAULT 'member';
+
+CREATE UNIQUE INDEX chat_members_owner_idx ON chat_members (chat_id) WHERE role = 'owner';
+
+CREATE TABLE pinned_messages (
+    message_id INTEGER PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    pinned_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    pinned_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX pinned_messages_chat_idx ON pinned_messages (chat_id, pinned_at);
+
+-- Folder rules now tell groups and direct chats apart by kind instead of member count.
+CREATE OR REPLACE FUNCTION chat_in_folder(p_folder_id INTEGER, p_chat_id INTEGER) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT EXISTS (
+        SELECT 1
+        FROM chat_folders f
+        JOIN chat_members cm ON cm.chat_id = p_chat_id AND cm.user_id = f.user_id
+        JOIN chats c ON c.id = p_chat_id
+        WHERE f.id = p_folder_id
+          AND cm.request_status = 'accepted'
+          AND NOT (f.exclude_muted AND cm.muted AND (cm.muted_until IS NULL OR cm.muted_until > NOW()))
+          AND (
+              EXISTS (SELECT 1 FROM chat_folder_chats fc WHERE fc.folder_id = f.id AND fc.chat_id = p_chat_id)
+              OR (f.include_unread AND unread_message_count(p_chat_id, f.user_id) > 0)
+              OR (f.include_groups AND c.is_group)
+              OR (f.include_direct AND NOT c.is_group)
+              OR EXISTS (
+                  SELECT 1
+                  FROM chat_folder_contacts fu
+                  JOIN chat_members other ON other.chat_id = p_chat_id AND other.user_id = fu.user_id
+                  WHERE fu.folder_id = f.id
+              )
+          )
+    )
+$$;
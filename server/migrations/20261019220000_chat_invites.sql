@@ -0,0 +1,31 @@
+-- Shareable links for joining group chats. The token is the only part of the link.
+CREATE TABLE chat_invites (
+    id SERIAL PRIMARY KEY,
+    chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    token VARCHAR(32) NOT NULL UNIQUE DEFAULT replace(gen_random_uuid()::TEXT, '-', ''),
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP,
+    max_uses INTEGER CHECK (max_uses > 0),
+    use_count INTEGER NOT NULL DEFAULT 0,
+    requires_approval BOOLEAN NOT NULL DEFAULT FALSE,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX chat_invites_chat_idx ON chat_invites (chat_id, created_at);
+
+-- Every use of a link. On links that require approval the user joins only once an admin approves.
+CREATE TYPE invite_use_status AS ENUM ('pending', 'approved', 'rejected');
+
+CREATE TABLE chat_invite_uses (
+    id SERIAL PRIMARY KEY,
+    invite_id INTEGER NOT NULL REFERENCES chat_invites(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status invite_use_status NOT NULL,
+    used_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    reviewed_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMP
+);
+
+CREATE INDEX chat_invite_uses_invite_idx ON chat_invite_uses (invite_id, used_at);
+CREATE UNIQUE INDEX chat_invite_uses_pending_idx ON chat_invite_uses (invite_id, user_id) WHERE status = 'pending';
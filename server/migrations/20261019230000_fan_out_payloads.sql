@@ -0,0 +1,9 @@
+-- Broadcasts too large for a NOTIFY payload. Instances are notified with the row id and read the
+-- broadcast from here; rows are removed once every instance had time to read them.
+CREATE TABLE fan_out_payloads (
+    id BIGSERIAL PRIMARY KEY,
+    envelope TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX fan_out_payloads_created_idx ON fan_out_payloads (created_at);
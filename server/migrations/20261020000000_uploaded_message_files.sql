@@ -0,0 +1,7 @@
+-- Marks files the server stored for a message itself. Only those are removed from disk when
+-- the message is deleted; a path a client merely sent may belong to someone else.
+ALTER TABLE messages
+    ADD COLUMN file_uploaded BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Before removing a file the server checks that no other message still points to it
+CREATE INDEX messages_file_name_idx ON messages (regexp_replace(file_path, '^.*/', ''));
@@ -0,0 +1,12 @@
+-- Until the recipient accepts a chat request, the requester may send only the one message
+-- that introduces them. Requesters who already wrote into a pending chat have used it up.
+ALTER TABLE chat_members
+    ADD COLUMN request_message_sent BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE chat_members cm
+SET request_message_sent = TRUE
+WHERE EXISTS (SELECT 1 FROM messages m WHERE m.chat_id = cm.chat_id AND m.user_id = cm.user_id)
+  AND EXISTS (
+      SELECT 1 FROM chat_members other
+      WHERE other.chat_id = cm.chat_id AND other.user_id <> cm.user_id AND other.request_status = 'pending'
+  );
@@ -0,0 +1,8 @@
+-- Files are served by name, so messages pointing to the same stored file are found by the
+-- name part of their path. A stored column keeps that lookup on a plain index.
+DROP INDEX messages_file_name_idx;
+
+ALTER TABLE messages
+    ADD COLUMN file_name TEXT GENERATED ALWAYS AS (regexp_replace(file_path, '^.*/', '')) STORED;
+
+CREATE INDEX messages_file_name_idx ON messages (file_name) WHERE file_name IS NOT NULL;
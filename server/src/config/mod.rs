@@ -0,0 +1,515 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use rocket::figment::Figment;
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Variables the server was configured with before the config file existed, and the keys they set.
+const LEGACY_VARIABLES: [(&str, &str); 3] = [
+    ("DATABASE_URL", "database.url"),
+    ("JWT_SECRET", "auth.jwt_secret"),
+    ("SEARCH_CONFIG", "search.text_search_config"),
+];
+
+/// A value that must not end up in logs. Formats as `[redacted]`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Server settings, loaded once at startup and kept in Rocket state.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub uploads: UploadConfig,
+    pub websocket: WebSocketConfig,
+    pub search: SearchConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub shutdown: ShutdownConfig,
+    pub fan_out: FanOutConfig,
+    pub persistence: PersistenceConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: Secret,
+    pub max_connections: u32,
+    pub acquire_timeout_seconds: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: Secret::default(),
+            max_connections: 10,
+            acquire_timeout_seconds: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub jwt_secret: Secret,
+    pub token_lifetime_seconds: usize,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: Secret::default(),
+            token_lifetime_seconds: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    pub directory: PathBuf,
+    pub max_file_bytes: u64,
+    pub max_avatar_bytes: u64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            directory: PathBuf::from("uploads"),
+            max_file_bytes: 20 * 1024 * 1024,
+            max_avatar_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Where an uploaded file is stored. Only the last component of `file_path` is used,
+    /// so a path sent by a client can't point outside the upload directory.
+    pub fn path_of(&self, file_path: &str) -> PathBuf {
+        let file_name = Path::new(file_path).file_name().unwrap_or_default();
+        self.directory.join(file_name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    pub bind_address: SocketAddr,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 9000)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub text_search_config: String,     // Postgres text search configuration, e.g. `english` or `simple`
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            text_search_config: "english".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub level: String,      // `tracing` filter directives, e.g. `info` or `info,sqlx=warn`; RUST_LOG takes precedence
+    pub format: LogFormat,
+    pub redact_content: bool,   // Hide message contents and file names in logs
+    pub otlp_endpoint: Option<String>,  // OTLP/gRPC collector to export spans to, e.g. `http://localhost:4317`
+    pub service_name: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::Pretty,
+            redact_content: true,
+            otlp_endpoint: None,
+            service_name: "chat-server".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+/// `/metrics` is off unless enabled, and then only reachable on its own address or with a token.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub bind_address: Option<SocketAddr>,   // Serve `/metrics` on a separate listener instead of the API port
+    pub token: Option<Secret>,  // Bearer token scrapers must send
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    pub deadline_seconds: u32,  // How long to wait for open requests and WebSocket connections after SIGTERM
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            deadline_seconds: 30,
+        }
+    }
+}
+
+/// How broadcasts reach sockets connected to other server instances.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FanOutConfig {
+    pub backend: FanOutBackend,
+    pub channel: String,    // NOTIFY or pub/sub channel shared by every instance
+    pub redis_url: Option<Secret>,  // Required with the `redis` backend, e.g. `redis://localhost:6379`
+}
+
+impl Default for FanOutConfig {
+    fn default() -> Self {
+        FanOutConfig {
+            backend: FanOutBackend::Postgres,
+            channel: "chat_fan_out".to_string(),
+            redis_url: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FanOutBackend {
+    Local,      // Single instance, nothing is published
+    Postgres,
+    Redis,
+}
+
+/// The pipeline that stores chat messages sent over WebSockets in batches.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    pub writers: usize,     // Parallel writers; each chat always goes to the same one
+    pub batch_size: usize,  // Most messages stored in one transaction
+    pub queue_capacity: usize,  // Messages waiting per writer before senders have to wait
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig {
+            writers: 4,
+            batch_size: 100,
+            queue_capacity: 1000,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `config.toml`, or the file named by `CONFIG_FILE`, and applies environment overrides
+    /// on top: first the legacy `DATABASE_URL`, `JWT_SECRET`, `CLIENT_URL` and `SEARCH_CONFIG`,
+    /// then `APP_`-prefixed variables with `__` between sections, e.g. `APP_DATABASE__MAX_CONNECTIONS`.
+    pub fn load() -> Result<Config, ConfigError> {
+        let mut problems = Vec::new();
+
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(file) => {
+                if !Path::new(&file).exists() {
+                    problems.push(format!("config file '{}' named by CONFIG_FILE does not exist", file));
+                }
+                file
+            }
+            Err(_) => DEFAULT_CONFIG_FILE.to_string(),
+        };
+
+        let mut figment = Figment::new().merge(Toml::file(&file));
+
+        // Старі змінні беремо як є, щоб секрети з цифр не перетворились на числа
+        for (variable, key) in LEGACY_VARIABLES {
+            if let Ok(value) = std::env::var(variable) {
+                figment = figment.merge(Serialized::default(key, value));
+            }
+        }
+
+        if let Ok(client_url) = std::env::var("CLIENT_URL") {
+            figment = figment.merge(Serialized::default("cors.allowed_origins", vec![client_url]));
+        }
+
+        figment = figment.merge(Env::prefixed("APP_").split("__"));
+
+        let config = match figment.extract::<Config>() {
+            Ok(config) => config,
+            Err(error) => {
+                problems.extend(error.into_iter().map(|error| error.to_string()));
+                return Err(ConfigError(problems));
+            }
+        };
+
+        problems.extend(config.problems());
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.database.url.expose().is_empty() {
+            problems.push("database.url is required (DATABASE_URL or APP_DATABASE__URL)".to_string());
+        }
+
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".to_string());
+        }
+
+        if self.auth.jwt_secret.expose().is_empty() {
+            problems.push("auth.jwt_secret is required (JWT_SECRET or APP_AUTH__JWT_SECRET)".to_string());
+        }
+
+        if self.auth.token_lifetime_seconds == 0 {
+            problems.push("auth.token_lifetime_seconds must be greater than 0".to_string());
+        }
+
+        if self.cors.allowed_origins.is_empty() {
+            problems.push("cors.allowed_origins needs at least one origin (CLIENT_URL or APP_CORS__ALLOWED_ORIGINS)".to_string());
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                problems.push(format!("cors.allowed_origins: '{}' is not an http(s) origin", origin));
+            }
+        }
+
+        if self.uploads.directory.as_os_str().is_empty() {
+            problems.push("uploads.directory must not be empty".to_string());
+        }
+
+        if self.uploads.max_file_bytes == 0 {
+            problems.push("uploads.max_file_bytes must be greater than 0".to_string());
+        }
+
+        if self.uploads.max_avatar_bytes == 0 {
+            problems.push("uploads.max_avatar_bytes must be greater than 0".to_string());
+        }
+
+        if self.search.text_search_config.trim().is_empty() {
+            problems.push("search.text_search_config must not be empty".to_string());
+        }
+
+        if let Err(error) = EnvFilter::try_new(&self.logging.level) {
+            problems.push(format!("logging.level: '{}' is not a valid filter ({})", self.logging.level, error));
+        }
+
+        if self.metrics.enabled && self.metrics.bind_address.is_none() && self.metrics.token.is_none() {
+            problems.push("metrics.enabled needs metrics.bind_address or metrics.token, so that /metrics isn't public".to_string());
+        }
+
+        if self.shutdown.deadline_seconds == 0 {
+            problems.push("shutdown.deadline_seconds must be greater than 0".to_string());
+        }
+
+        if self.fan_out.channel.is_empty() || !self.fan_out.channel.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            problems.push(format!("fan_out.channel: '{}' may only contain letters, digits and underscores", self.fan_out.channel));
+        }
+
+        if self.fan_out.backend == FanOutBackend::Redis && self.fan_out.redis_url.is_none() {
+            problems.push("fan_out.backend = \"redis\" needs fan_out.redis_url".to_string());
+        }
+
+        if self.persistence.writers == 0 {
+            problems.push("persistence.writers must be at least 1".to_string());
+        }
+
+        if self.persistence.batch_size == 0 {
+            problems.push("persistence.batch_size must be at least 1".to_string());
+        }
+
+        if self.persistence.queue_capacity == 0 {
+            problems.push("persistence.queue_capacity must be at least 1".to_string());
+        }
+
+        if self.metrics.token.as_ref().is_some_and(|token| token.expose().is_empty()) {
+            problems.push("metrics.token must not be empty".to_string());
+        }
+
+        if let Some(endpoint) = &self.logging.otlp_endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                problems.push(format!("logging.otlp_endpoint: '{}' is not an http(s) URL", endpoint));
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::Jail;
+
+    type BreakConfig = fn(&mut Config);
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.database.url = Secret("postgres://localhost/chat".to_string());
+        config.auth.jwt_secret = Secret("jwt-secret".to_string());
+        config.cors.allowed_origins = vec!["http://localhost:3000".to_string()];
+        config
+    }
+
+    fn has_problem(config: &Config, key: &str) -> bool {
+        config.problems().iter().any(|problem| problem.starts_with(key))
+    }
+
+    #[test]
+    fn defaults_with_required_values_are_valid() {
+        assert_eq!(valid().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_required_values_are_all_reported() {
+        let config = Config::default();
+
+        assert!(has_problem(&config, "database.url"));
+        assert!(has_problem(&config, "auth.jwt_secret"));
+        assert!(has_problem(&config, "cors.allowed_origins"));
+        assert_eq!(config.problems().len(), 3);
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let cases: [(&str, BreakConfig); 9] = [
+            ("database.max_connections", |config| config.database.max_connections = 0),
+            ("cors.allowed_origins", |config| config.cors.allowed_origins = vec!["localhost:3000".to_string()]),
+            ("logging.level", |config| config.logging.level = "info,=".to_string()),
+            ("logging.otlp_endpoint", |config| config.logging.otlp_endpoint = Some("localhost:4317".to_string())),
+            ("metrics.enabled", |config| config.metrics.enabled = true),
+            ("metrics.token", |config| config.metrics.token = Some(Secret::default())),
+            ("fan_out.channel", |config| config.fan_out.channel = "chat-fan-out".to_string()),
+            ("fan_out.backend", |config| config.fan_out.backend = FanOutBackend::Redis),
+            ("persistence.writers", |config| config.persistence.writers = 0),
+        ];
+
+        for (key, break_config) in cases {
+            let mut config = valid();
+            break_config(&mut config);
+
+            assert!(has_problem(&config, key), "expected a problem with {}, got {:?}", key, config.problems());
+        }
+    }
+
+    #[test]
+    fn secrets_are_redacted_in_debug_output() {
+        let mut config = valid();
+        config.metrics.token = Some(Secret("metrics-token".to_string()));
+        config.fan_out.redis_url = Some(Secret("redis://:password@localhost".to_string()));
+
+        let output = format!("{:?}", config);
+
+        assert!(output.contains("[redacted]"));
+        for secret in ["postgres://localhost/chat", "jwt-secret", "metrics-token", "password"] {
+            assert!(!output.contains(secret), "{} leaked into {}", secret, output);
+        }
+    }
+
+    // Помилка Jail велика, але її тип задає figment
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn environment_overrides_the_file_in_order() {
+        // Jail дає тесту власні змінні середовища й робочий каталог і відновлює їх після нього
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+            jail.create_file("chat.toml", r#"
+                [database]
+                url = "postgres://localhost/from-file"
+                max_connections = 5
+
+                [auth]
+                jwt_secret = "from-file"
+
+                [cors]
+                allowed_origins = ["http://localhost:3000"]
+
+                [search]
+                text_search_config = "german"
+            "#)?;
+
+            jail.set_env("CONFIG_FILE", "chat.toml");
+            jail.set_env("JWT_SECRET", "123456");
+            jail.set_env("SEARCH_CONFIG", "simple");
+            jail.set_env("APP_DATABASE__MAX_CONNECTIONS", "7");
+
+            let legacy = Config::load().unwrap();
+
+            // Старі змінні важать більше за файл, і секрет з цифр лишається рядком
+            assert_eq!(legacy.database.url.expose(), "postgres://localhost/from-file");
+            assert_eq!(legacy.database.max_connections, 7);
+            assert_eq!(legacy.auth.jwt_secret.expose(), "123456");
+            assert_eq!(legacy.search.text_search_config, "simple");
+
+            // Змінні з префіксом APP_ важать більше за старі
+            jail.set_env("APP_AUTH__JWT_SECRET", "from-app");
+            assert_eq!(Config::load().unwrap().auth.jwt_secret.expose(), "from-app");
+
+            Ok(())
+        });
+    }
+}
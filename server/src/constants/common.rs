@@ -1,2 +1,30 @@
 pub const BCRYPT_COST: u32 = 12;
-pub const JWT_EXPIRATION_SECONDS: usize = 2592000;
\ No newline at end of file
+pub const TYPING_TIMEOUT_SECONDS: u64 = 6;
+pub const AWAY_AFTER_SECONDS: u64 = 300;
+pub const PRESENCE_SWEEP_INTERVAL_SECONDS: u64 = 1;
+pub const MESSAGE_SNIPPET_LENGTH: usize = 100;
+pub const MESSAGE_EDIT_WINDOW_SECONDS: i64 = 172800;
+pub const THREAD_PAGE_SIZE: i64 = 50;
+pub const MAX_THREAD_PAGE_SIZE: i64 = 100;
+pub const MAX_REACTION_BYTES: usize = 32;
+pub const SEARCH_PAGE_SIZE: i64 = 20;
+pub const MAX_SEARCH_PAGE_SIZE: i64 = 100;
+pub const MIN_USER_SEARCH_LENGTH: usize = 3;
+pub const USER_SEARCH_PAGE_SIZE: i64 = 20;
+pub const MAX_USER_SEARCH_PAGE_SIZE: i64 = 50;
+pub const MAX_USER_SEARCH_RESULTS: i64 = 200;
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+pub const MAX_BIO_LENGTH: usize = 500;
+pub const MAX_STATUS_TEXT_LENGTH: usize = 100;
+pub const AVATAR_SIZE: u32 = 256;
+pub const MAX_PINNED_CHATS: i64 = 5;
+pub const MAX_CHAT_FOLDERS: i64 = 20;
+pub const MAX_FOLDER_NAME_LENGTH: usize = 64;
+pub const MAX_CHAT_TITLE_LENGTH: usize = 128;
+pub const MAX_GROUP_MEMBERS: usize = 200;
+pub const FAN_OUT_RECONNECT_SECONDS: u64 = 1;
+pub const MAX_NOTIFY_PAYLOAD_BYTES: usize = 7900;   // Postgres rejects NOTIFY payloads of 8000 bytes and more
+pub const FAN_OUT_PAYLOAD_RETENTION_SECONDS: i64 = 300;
+pub const CHAT_AUDIENCE_TTL_SECONDS: u64 = 60;    // Backstop in case an invalidation from another instance is lost
+pub const PRESENCE_SNAPSHOT_SECONDS: u64 = 10;
+pub const REMOTE_PRESENCE_TTL_SECONDS: u64 = 35;   // An instance that missed three snapshots is gone
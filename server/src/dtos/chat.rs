@@ -1,24 +1,144 @@
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use sqlx::types::chrono::{NaiveDateTime};
+use crate::dtos::user::UserSummary;
 
-#[derive(Deserialize)]
+/// Each participant is given either by email or by the id of a directory search result.
+/// An email without an exact match falls back to the directory search.
+#[derive(Deserialize, JsonSchema)]
 pub struct CreateChatRequest {
-    pub user1_email: String,
-    pub user2_email: String,
+    pub user1_email: Option<String>,
+    pub user1_id: Option<i32>,
+    pub user2_email: Option<String>,
+    pub user2_id: Option<i32>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Direct chats have `user1`/`user2`, groups a title, an avatar and any number of members.
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ChatResponse {
     pub id: i32,
-    pub user1_id: i32,
-    pub user2_id: i32,
-    pub user1_email: Option<String>,
-    pub user2_email: Option<String>,
+    pub is_group: bool,
+    pub user1_id: Option<i32>,
+    pub user2_id: Option<i32>,
+    pub user1: Option<UserSummary>,
+    pub user2: Option<UserSummary>,
+    pub title: Option<String>,
+    pub avatar_url: Option<String>,
+    pub member_count: i64,
     pub created_at: Option<NaiveDateTime>,
+    pub role: ChatRole,     // The viewer's own role
+    pub request_status: ChatRequestStatus,  // The viewer's own membership state
+    pub settings: ChatSettings,
+    pub unread_count: i64,
+    pub last_read_message_id: Option<i32>,
+    pub last_message: Option<LastMessagePreview>,
+    pub last_activity_at: Option<NaiveDateTime>,
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "chat_role", rename_all = "snake_case")]
+pub enum ChatRole {
+    Owner,
+    Admin,
+    Member,
+    ReadOnly,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateGroupRequest {
+    pub title: String,
+    pub member_ids: Vec<i32>,   // Everyone besides the creator, who becomes the owner
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateChatRequest {
+    pub title: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AddMemberRequest {
+    pub user_id: i32,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateMemberRoleRequest {
+    pub role: ChatRole,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TransferOwnershipRequest {
+    pub user_id: i32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ChatMemberResponse {
+    pub user: UserSummary,
+    pub role: ChatRole,
+    pub request_status: ChatRequestStatus,
+    pub joined_at: NaiveDateTime,
+}
+
+/// The viewer's own settings for a chat.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct ChatSettings {
+    pub muted: bool,
+    pub muted_until: Option<NaiveDateTime>,     // Muted indefinitely when `muted` is set without it
+    pub archived: bool,
+    pub pinned_position: Option<i32>,
+    pub favourite: bool,
+}
+
+/// Omitted fields stay unchanged. Archiving a chat unpins it and pinning unarchives it.
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateChatSettingsRequest {
+    pub muted: Option<bool>,
+    pub muted_until: Option<NaiveDateTime>,
+    pub archived: Option<bool>,
+    pub pinned: Option<bool>,
+    pub favourite: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReorderPinnedChatsRequest {
+    pub chat_ids: Vec<i32>,     // Every pinned chat, in the new order
+}
+
+/// Pending chats were started by a non-contact and wait in the recipient's requests inbox.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "chat_request_status", rename_all = "lowercase")]
+pub enum ChatRequestStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
 
-#[derive(Serialize)]
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRequestAction {
+    Accept,
+    Decline,
+    Block,  // Declines and blocks the requester
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RespondToChatRequest {
+    pub action: ChatRequestAction,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LastMessagePreview {
+    pub id: i32,
+    pub content: String,    // Shortened to MESSAGE_SNIPPET_LENGTH characters
+    pub sender: UserSummary,
+    pub message_type: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub deleted: bool,
+}
+
+
+#[derive(Serialize, JsonSchema)]
 pub struct Chat {
     pub id: i32,
     pub user1_id: i32,
@@ -26,7 +146,7 @@ pub struct Chat {
     pub created_at: NaiveDateTime,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ChatWithUsers {
     pub id: i32,
     pub user1_email: String,
@@ -35,13 +155,83 @@ pub struct ChatWithUsers {
 }
 
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct MessageResponse {
     pub id: i32,
     pub chat_id: i32,
     pub user_id: i32,
+    pub sender: UserSummary,
     pub content: String,
     pub created_at: Option<NaiveDateTime>,
     pub file_path: Option<String>,
-    pub message_type: Option<String>
-}
\ No newline at end of file
+    pub message_type: Option<String>,
+    pub status: Option<MessageStatus>,  // Only set on the viewer's own messages
+    pub edited_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,  // Tombstone: content and file are blanked
+    pub reply_to_message_id: Option<i32>,
+    pub reply_to: Option<ReplyPreview>,
+    pub thread_root_id: Option<i32>,
+    pub reply_count: i32,
+    pub last_reply_at: Option<NaiveDateTime>,
+    pub reactions: Vec<ReactionSummary>,
+    pub pinned_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub count: i64,
+    pub reacted: bool,  // Whether the viewer is among those who reacted
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReactionRequest {
+    pub emoji: String,
+}
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct ReplyPreview {
+    pub message_id: i32,
+    pub user_id: i32,
+    pub content: String,    // Snippet; empty once the original is deleted
+    pub message_type: Option<String>,
+    pub deleted: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ThreadResponse {
+    pub root: MessageResponse,
+    pub replies: Vec<MessageResponse>,
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    Sent,
+    Delivered,
+    Read,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MarkReadRequest {
+    pub message_id: i32,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct EditMessageRequest {
+    pub content: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MessageEditResponse {
+    pub previous_content: String,
+    pub edited_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteScope {
+    Me,
+    Everyone,
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+#[derive(Serialize, JsonSchema)]
+pub struct ChatFolderResponse {
+    pub id: i32,
+    pub name: String,
+    pub position: i32,
+    pub include_unread: bool,
+    pub include_groups: bool,
+    pub include_direct: bool,
+    pub exclude_muted: bool,
+    pub chat_ids: Vec<i32>,     // Chats added manually
+    pub contact_ids: Vec<i32>,  // Every chat with these users is included
+    pub unread_count: i64,      // Unread messages over all chats in the folder
+    pub unread_chats: i64,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateChatFolderRequest {
+    pub name: String,
+    #[serde(default)]
+    pub include_unread: bool,
+    #[serde(default)]
+    pub include_groups: bool,
+    #[serde(default)]
+    pub include_direct: bool,
+    #[serde(default)]
+    pub exclude_muted: bool,
+    #[serde(default)]
+    pub chat_ids: Vec<i32>,
+    #[serde(default)]
+    pub contact_ids: Vec<i32>,
+}
+
+/// Omitted fields stay unchanged; given lists replace the current ones.
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateChatFolderRequest {
+    pub name: Option<String>,
+    pub include_unread: Option<bool>,
+    pub include_groups: Option<bool>,
+    pub include_direct: Option<bool>,
+    pub exclude_muted: Option<bool>,
+    pub chat_ids: Option<Vec<i32>>,
+    pub contact_ids: Option<Vec<i32>>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReorderChatFoldersRequest {
+    pub folder_ids: Vec<i32>,   // Every folder, in the new order
+}
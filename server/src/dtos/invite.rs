@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use sqlx::types::chrono::NaiveDateTime;
+use crate::dtos::user::UserSummary;
+
+#[derive(Serialize, JsonSchema)]
+pub struct InviteResponse {
+    pub id: i32,
+    pub chat_id: i32,
+    pub token: String,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub max_uses: Option<i32>,
+    pub use_count: i32,     // Join requests count as uses even before they are reviewed
+    pub requires_approval: bool,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+/// A link without `expires_at` or `max_uses` stays valid until it is revoked.
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateInviteRequest {
+    pub expires_at: Option<NaiveDateTime>,
+    pub max_uses: Option<i32>,
+    #[serde(default)]
+    pub requires_approval: bool,
+}
+
+/// What a user sees about a chat before joining it through a link.
+#[derive(Serialize, JsonSchema)]
+pub struct InvitePreview {
+    pub chat_id: i32,
+    pub title: String,
+    pub avatar_url: Option<String>,
+    pub member_count: i64,
+    pub requires_approval: bool,
+    pub is_member: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, FromFormField, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "invite_use_status", rename_all = "lowercase")]
+pub enum InviteUseStatus {
+    Pending,    // Waiting for an admin
+    Approved,
+    Rejected,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct InviteUseResponse {
+    pub id: i32,
+    pub invite_id: i32,
+    pub user: UserSummary,
+    pub status: InviteUseStatus,
+    pub used_at: NaiveDateTime,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct JoinChatResponse {
+    pub chat_id: i32,
+    pub status: InviteUseStatus,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinRequestAction {
+    Approve,
+    Reject,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReviewJoinRequest {
+    pub action: JoinRequestAction,
+}
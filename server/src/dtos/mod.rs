@@ -2,3 +2,7 @@ pub mod user;
 pub mod responses;
 pub mod others;
 pub mod chat;
+pub mod search;
+pub mod folder;
+pub mod invite;
+pub mod health;
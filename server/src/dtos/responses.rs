@@ -1,12 +1,13 @@
 use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use crate::dtos::user::UserWithoutPassword;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct MessageOnlyResponse {
     pub message: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct LoginResponse {
     pub message: String,
     pub token: Option<String>,
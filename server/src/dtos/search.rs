@@ -0,0 +1,21 @@
+use serde::Serialize;
+use schemars::JsonSchema;
+use sqlx::types::chrono::NaiveDateTime;
+
+#[derive(Serialize, JsonSchema)]
+pub struct SearchResult {
+    pub message_id: i32,
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub message_type: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub file_path: Option<String>,
+    pub highlight: String,  // HTML-escaped text with matches wrapped in <mark>
+    pub rank: f32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    pub next_cursor: Option<String>,
+}
@@ -1,14 +1,107 @@
 use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use sqlx::types::chrono::{NaiveDateTime};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct User {
     pub id: Option<i32>,
     pub email: String,
     pub password: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct UserWithoutPassword {
     pub id: Option<i32>,
     pub email: String,
 }
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PresenceResponse {
+    pub user_id: i32,
+    pub status: Option<PresenceStatus>,     // None when the user hides their presence
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+/// Who may open a direct chat with the user.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "direct_chat_policy", rename_all = "lowercase")]
+pub enum DirectChatPolicy {
+    Everyone,
+    Contacts,
+    Nobody,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PrivacySettings {
+    pub hide_presence: bool,
+    pub discoverable: bool,     // Whether strangers can find the user in the directory
+    pub direct_chat_policy: DirectChatPolicy,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdatePrivacySettingsRequest {
+    pub hide_presence: Option<bool>,
+    pub discoverable: Option<bool>,
+    pub direct_chat_policy: Option<DirectChatPolicy>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BlockedUser {
+    pub user: UserSummary,
+    pub blocked_at: NaiveDateTime,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct UserSearchResponse {
+    pub users: Vec<UserSummary>,
+    pub next_offset: Option<i64>,
+}
+
+/// Compact description of a user shown wherever they appear instead of their email.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct UserSummary {
+    pub id: i32,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+    pub status_text: Option<String>,
+}
+
+impl UserSummary {
+    pub fn new(id: i32, display_name: String, avatar_path: Option<String>, status_text: Option<String>) -> Self {
+        UserSummary {
+            id,
+            display_name,
+            avatar_url: avatar_path.map(|path| format!("/api/files/{}", path)),
+            status_text,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct UserProfileResponse {
+    pub id: i32,
+    pub email: Option<String>,  // Only in the user's own profile
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub status_text: Option<String>,
+    pub status_expires_at: Option<NaiveDateTime>,
+}
+
+/// Omitted fields stay unchanged, empty strings clear them.
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateProfileRequest {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub status_text: Option<String>,
+    pub status_expires_at: Option<NaiveDateTime>,
+}
@@ -0,0 +1,274 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{MediaType, RefOr, Response as OpenApiResponse, Responses};
+use rocket_okapi::response::OpenApiResponderInner;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::fairings::request_id::RequestId;
+use crate::fairings::request_span::request_span;
+
+/// Postgres error codes the API reports as client errors.
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const CHECK_VIOLATION: &str = "23514";
+
+#[derive(Serialize, JsonSchema, Clone, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The error statuses listed for every endpoint in the OpenAPI document.
+const DOCUMENTED_ERRORS: [(Status, &str); 8] = [
+    (Status::BadRequest, "`bad_request`: the request can't be processed as sent"),
+    (Status::Unauthorized, "`unauthorized`: the bearer token is missing or invalid"),
+    (Status::Forbidden, "`forbidden`: the user isn't allowed to do this"),
+    (Status::NotFound, "`not_found`: the resource doesn't exist or isn't visible to the user"),
+    (Status::Conflict, "`conflict`: the request clashes with the current state"),
+    (Status::UnprocessableEntity, "`validation_failed`: the body is malformed or `fields` lists the invalid ones"),
+    (Status::TooManyRequests, "`too_many_requests`: the client is sending requests too fast"),
+    (Status::InternalServerError, "`internal_error`: the server failed to handle the request"),
+];
+
+/// Every error the API returns. Each variant has its own HTTP status and a stable `code`
+/// clients can match on; the message is meant for people.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Validation { message: String, fields: Vec<FieldError> },
+    TooManyRequests(String),
+    Internal(String),
+    /// An unexpected database failure; `source` is logged when the response is sent.
+    Database { message: String, source: sqlx::Error },
+}
+
+/// The JSON body of every error response.
+#[derive(Serialize, JsonSchema)]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "<[FieldError]>::is_empty")]
+    fields: &'a [FieldError],
+    request_id: &'a str,
+}
+
+impl ApiError {
+    /// A validation failure of a single field.
+    pub fn invalid_field(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+
+        ApiError::Validation {
+            fields: vec![FieldError { field: field.to_string(), message: message.clone() }],
+            message,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Validation { .. } => Status::UnprocessableEntity,
+            ApiError::TooManyRequests(_) => Status::TooManyRequests,
+            ApiError::Internal(_) | ApiError::Database { .. } => Status::InternalServerError,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation { .. } => "validation_failed",
+            ApiError::TooManyRequests(_) => "too_many_requests",
+            ApiError::Internal(_) | ApiError::Database { .. } => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Validation { message, .. }
+            | ApiError::TooManyRequests(message)
+            | ApiError::Internal(message)
+            | ApiError::Database { message, .. } => message,
+        }
+    }
+
+    /// Maps a database error, keeping `message` for the failures the client can do nothing about.
+    pub fn database(error: sqlx::Error, message: &str) -> Self {
+        if let sqlx::Error::RowNotFound = error {
+            return ApiError::NotFound("Record not found".to_string());
+        }
+
+        if let Some(db_error) = error.as_database_error() {
+            match db_error.code().as_deref() {
+                Some(UNIQUE_VIOLATION) => return ApiError::Conflict("Record already exists".to_string()),
+                Some(FOREIGN_KEY_VIOLATION) => return ApiError::NotFound("Referenced record not found".to_string()),
+                Some(CHECK_VIOLATION) => return ApiError::BadRequest("Value is out of the allowed range".to_string()),
+                _ => {}
+            }
+        }
+
+        ApiError::Database { message: message.to_string(), source: error }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        ApiError::database(error, "Database error")
+    }
+}
+
+/// Attaches the message an unexpected database error is reported with.
+pub trait ErrorContext<T> {
+    fn context(self, message: &str) -> Result<T, ApiError>;
+}
+
+impl<T> ErrorContext<T> for Result<T, sqlx::Error> {
+    fn context(self, message: &str) -> Result<T, ApiError> {
+        self.map_err(|error| ApiError::database(error, message))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let request_id = RequestId::of(request);
+
+        if self.status().code >= 500 {
+            request_span(request).in_scope(|| match &self {
+                ApiError::Database { message, source } => tracing::error!(code = self.code(), error = %source, "{}", message),
+                _ => tracing::error!(code = self.code(), "{}", self.message()),
+            });
+        }
+
+        let fields = match &self {
+            ApiError::Validation { fields, .. } => fields.as_slice(),
+            _ => &[],
+        };
+
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            fields,
+            request_id,
+        };
+
+        Response::build_from(Json(body).respond_to(request)?)
+            .status(self.status())
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for ApiError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let schema = gen.json_schema::<ErrorBody>();
+        let mut responses = Responses::default();
+
+        for (status, description) in DOCUMENTED_ERRORS {
+            let mut content = rocket_okapi::okapi::Map::new();
+            content.insert("application/json".to_string(), MediaType { schema: Some(schema.clone()), ..Default::default() });
+
+            let response = OpenApiResponse {
+                description: description.to_string(),
+                content,
+                ..Default::default()
+            };
+            responses.responses.insert(status.code.to_string(), RefOr::Object(response));
+        }
+
+        Ok(responses)
+    }
+}
+
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::BadRequest("The request could not be understood".to_string())
+}
+
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthorized("User unauthorized!".to_string())
+}
+
+#[catch(403)]
+pub fn forbidden() -> ApiError {
+    ApiError::Forbidden("Access denied".to_string())
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    ApiError::NotFound(format!("Nothing found at {}", request.uri().path()))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::Validation {
+        message: "The request body is malformed or has invalid fields".to_string(),
+        fields: Vec::new(),
+    }
+}
+
+#[catch(429)]
+pub fn too_many_requests() -> ApiError {
+    ApiError::TooManyRequests("Too many requests, try again later".to_string())
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::Internal("Internal server error".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use super::*;
+
+    #[test]
+    fn missing_rows_are_not_found() {
+        let error = ApiError::database(sqlx::Error::RowNotFound, "Failed to load chat");
+
+        assert_eq!(error.status(), Status::NotFound);
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn unexpected_failures_keep_the_database_error_as_source() {
+        let error = ApiError::database(sqlx::Error::PoolTimedOut, "Failed to load chat");
+
+        assert_eq!(error.status(), Status::InternalServerError);
+        assert_eq!(error.code(), "internal_error");
+        assert_eq!(error.message(), "Failed to load chat");
+        assert!(matches!(error.source().and_then(|source| source.downcast_ref()), Some(sqlx::Error::PoolTimedOut)));
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Instant;
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::Request;
+use rocket::response::Response;
+use crate::metrics::METRICS;
+
+struct RequestStarted(Instant);
+
+/// Counts requests and times them per route. Requests that matched no route share one label.
+pub struct MetricsFairing;
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStarted(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let RequestStarted(started) = request.local_cache(|| RequestStarted(Instant::now()));
+        let route = request.route().map(|route| route.uri.to_string());
+        let route = route.as_deref().unwrap_or("unmatched");
+        let method = request.method().as_str();
+
+        METRICS.http_requests
+            .with_label_values(&[route, method, &response.status().code.to_string()])
+            .inc();
+        METRICS.http_request_duration
+            .with_label_values(&[route, method])
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod metrics;
+pub mod request_id;
+pub mod request_span;
@@ -0,0 +1,59 @@
+use std::time::Instant;
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::Request;
+use rocket::response::Response;
+use tracing::field::Empty;
+use tracing::{info, info_span, Span};
+use crate::fairings::request_id::RequestId;
+
+struct RequestSpan {
+    span: Span,
+    started: Instant,
+}
+
+/// The span opened for the request by `RequestSpanFairing`.
+pub fn request_span(request: &Request<'_>) -> Span {
+    request
+        .local_cache(|| RequestSpan { span: Span::none(), started: Instant::now() })
+        .span
+        .clone()
+}
+
+/// Opens a span for every request and logs how it ended. Only the matched route is recorded,
+/// never the path or query themselves: those can hold invite tokens and search terms.
+/// Attach after `RequestIdFairing`, so the span gets the id a proxy sent.
+pub struct RequestSpanFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestSpanFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request span",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let span = info_span!(
+            "request",
+            request_id = %RequestId::of(request),
+            method = %request.method(),
+            route = Empty,
+            status = Empty,
+        );
+
+        request.local_cache(|| RequestSpan { span, started: Instant::now() });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let RequestSpan { span, started } = request.local_cache(|| RequestSpan { span: Span::none(), started: Instant::now() });
+
+        // Запит без маршруту (404) теж логуємо, але без шляху
+        let route = request.route().map(|route| route.uri.to_string());
+        span.record("route", route.as_deref().unwrap_or("-"));
+        span.record("status", response.status().code);
+
+        span.in_scope(|| info!(latency_ms = started.elapsed().as_millis() as u64, "Request finished"));
+    }
+}
@@ -1,9 +1,14 @@
 use rocket::http::Status;
 use rocket::request::{self, FromRequest, Request};
 use rocket::outcome::{Outcome};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{Object, SecurityRequirement, SecurityScheme, SecuritySchemeData};
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
-use crate::environment::Env;
+use sqlx::PgPool;
+use crate::config::{AuthConfig, Config};
+use crate::metrics::METRICS;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
@@ -19,6 +24,29 @@ pub enum AuthError {
 pub struct AuthGuard {
     #[allow(dead_code)]
     claims: Claims,
+    pub user_id: i32,
+}
+
+/// Validates a JWT and returns the email stored in its `sub` claim.
+pub fn decode_token(auth: &AuthConfig, token: &str) -> Option<String> {
+    let token = token.trim_start_matches("Bearer ");
+    let decoding_key = DecodingKey::from_secret(auth.jwt_secret.expose().as_bytes());
+
+    decode::<Claims>(token, &decoding_key, &Validation::default())
+        .ok()
+        .map(|decoded| decoded.claims.sub)
+}
+
+/// Resolves the id of the user a token was issued for.
+pub async fn authenticate(pool: &PgPool, auth: &AuthConfig, token: &str) -> Option<i32> {
+    let email = decode_token(auth, token)?;
+
+    sqlx::query!("SELECT id FROM users WHERE email = $1", email)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|user| user.id)
 }
 
 #[rocket::async_trait]
@@ -30,19 +58,65 @@ impl<'r> FromRequest<'r> for AuthGuard {
 
         match token {
             Some(token) => {
-                let token = token.trim_start_matches("Bearer ");
+                let config = match request.rocket().state::<Config>() {
+                    Some(config) => config,
+                    None => return Outcome::Error((Status::InternalServerError, AuthError::Invalid)),
+                };
+
+                let email = match decode_token(&config.auth, token) {
+                    Some(email) => email,
+                    None => {
+                        METRICS.auth_failures.with_label_values(&["invalid_token"]).inc();
+                        return Outcome::Error((Status::Unauthorized, AuthError::Invalid));
+                    }
+                };
 
-                let decoding_key = DecodingKey::from_secret(Env::jwt_secret().as_ref());
-                let validation = Validation::default();
+                let pool = match request.rocket().state::<PgPool>() {
+                    Some(pool) => pool,
+                    None => return Outcome::Error((Status::InternalServerError, AuthError::Invalid)),
+                };
 
-                match decode::<Claims>(token, &decoding_key, &validation) {
-                    Ok(decoded) => {
-                        Outcome::Success(AuthGuard { claims: decoded.claims })
+                match sqlx::query!("SELECT id FROM users WHERE email = $1", email)
+                    .fetch_optional(pool)
+                    .await
+                {
+                    Ok(Some(user)) => Outcome::Success(AuthGuard {
+                        claims: Claims { sub: email },
+                        user_id: user.id,
+                    }),
+                    _ => {
+                        METRICS.auth_failures.with_label_values(&["unknown_user"]).inc();
+                        Outcome::Error((Status::Unauthorized, AuthError::Invalid))
                     }
-                    Err(_) => Outcome::Error((Status::Unauthorized, AuthError::Invalid)),
                 }
             }
-            None => Outcome::Error((Status::Unauthorized, AuthError::Missing)),
+            None => {
+                METRICS.auth_failures.with_label_values(&["missing_token"]).inc();
+                Outcome::Error((Status::Unauthorized, AuthError::Missing))
+            }
         }
     }
 }
+
+/// Documents every route taking an `AuthGuard` as requiring a bearer token.
+impl<'r> OpenApiFromRequest<'r> for AuthGuard {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some("The token returned by `POST /api/users/login`, sent as `Authorization: Bearer <token>`.".to_string()),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_string(),
+                bearer_format: Some("JWT".to_string()),
+            },
+            extensions: Object::default(),
+        };
+
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert("bearer".to_string(), Vec::new());
+
+        Ok(RequestHeaderInput::Security("bearer".to_string(), scheme, requirement))
+    }
+}
@@ -0,0 +1,39 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::outcome::Outcome;
+use crate::config::Config;
+
+/// Lets a scraper read `/metrics`: requires the bearer token when `metrics.token` is set.
+pub struct MetricsAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match request.rocket().state::<Config>() {
+            Some(config) => config,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        if !config.metrics.enabled {
+            return Outcome::Error((Status::NotFound, ()));
+        }
+
+        let token = match &config.metrics.token {
+            Some(token) => token,
+            None => return Outcome::Success(MetricsAccess),
+        };
+
+        let sent = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        if sent == Some(token.expose()) {
+            Outcome::Success(MetricsAccess)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
@@ -0,0 +1,16 @@
+#[macro_use]
+extern crate rocket;
+
+pub mod routes;
+pub mod dtos;
+pub mod constants;
+pub mod config;
+pub mod errors;
+pub mod fairings;
+pub mod utils;
+pub mod guards;
+pub mod lifecycle;
+pub mod websockets;
+pub mod services;
+pub mod metrics;
+pub mod telemetry;
@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::websockets::hub::Hub;
+
+/// State shared by the HTTP and WebSocket servers to report readiness and shut down together.
+#[derive(Default)]
+pub struct Lifecycle {
+    shutdown: CancellationToken,
+    shutdown_started_at: OnceLock<Instant>,
+    connections: TaskTracker,
+    websocket_listening: AtomicBool,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Resolves once shutdown has started.
+    pub async fn shutdown_started(&self) {
+        self.shutdown.cancelled().await
+    }
+
+    /// Tracks the tasks serving WebSocket connections, so shutdown can wait for them.
+    pub fn connections(&self) -> &TaskTracker {
+        &self.connections
+    }
+
+    pub fn is_websocket_listening(&self) -> bool {
+        self.websocket_listening.load(Ordering::Relaxed)
+    }
+
+    pub fn set_websocket_listening(&self, listening: bool) {
+        self.websocket_listening.store(listening, Ordering::Relaxed);
+    }
+
+    /// Stops accepting WebSocket connections and asks every open socket to close.
+    /// Only the first call has an effect.
+    pub fn begin_shutdown(&self, hub: &Hub) {
+        let mut first = false;
+        self.shutdown_started_at.get_or_init(|| {
+            first = true;
+            Instant::now()
+        });
+
+        if !first {
+            return;
+        }
+
+        self.shutdown.cancel();
+        self.connections.close();
+
+        let close = Message::Close(Some(CloseFrame {
+            code: CloseCode::Away,
+            reason: "Server is shutting down".into(),
+        }));
+        hub.send_to_all(&close);
+
+        tracing::info!(connections = self.connections.len(), "Shutting down, closing WebSocket connections");
+    }
+
+    /// Waits until every connection task has ended, but no longer than `deadline` counted from
+    /// the start of shutdown. A connection finishes the frames it already read, including saving
+    /// messages, before its task ends.
+    pub async fn finish_shutdown(&self, hub: &Hub, deadline: Duration) {
+        self.begin_shutdown(hub);
+
+        let elapsed = self.shutdown_started_at.get().map(Instant::elapsed).unwrap_or_default();
+
+        if tokio::time::timeout(deadline.saturating_sub(elapsed), self.connections.wait()).await.is_err() {
+            tracing::warn!(connections = self.connections.len(), "WebSocket connections still open at the shutdown deadline");
+        }
+    }
+}
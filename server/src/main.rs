@@ -1,45 +1,42 @@
 #[macro_use]
 extern crate rocket;
 
-mod routes;
-mod dtos;
-mod constants;
-mod environment;
-mod utils;
-mod guards;
-mod websockets;
-
 use dotenv::dotenv;
 use rocket::{Rocket, Build};
-use rocket::serde::json::Json;
-use rocket::catchers;
+use rocket::fairing::AdHoc;
 use rocket_cors::{AllowedOrigins, CorsOptions};
-use sqlx::PgPool;
-use crate::environment::{Env};
-use routes::auth::{register, login, logout};
-use crate::dtos::responses::MessageOnlyResponse;
-use crate::routes::chats::{create_chat, get_chat_by_id, get_chat_messages, get_chats};
-use crate::routes::users::{get_user, get_users};
+use rocket::State;
+use sqlx::postgres::PgPoolOptions;
+use server::{routes, telemetry};
+use server::config::Config;
+use server::errors::{bad_request, forbidden, internal_error, not_found, too_many_requests, unauthorized, unprocessable_entity};
+use server::fairings::metrics::MetricsFairing;
+use server::fairings::request_id::RequestIdFairing;
+use server::fairings::request_span::RequestSpanFairing;
+use server::lifecycle::Lifecycle;
+use server::routes::{auth, chats, contacts, docs, folders, groups, health, invites, messages, search, users};
+use rocket_okapi::settings::OpenApiSettings;
+use rocket_okapi::swagger_ui::make_swagger_ui;
+use rocket_okapi::{mount_endpoints_and_merged_docs, openapi_get_routes_spec};
 
-use crate::websockets::server::websocket_server;
+use server::websockets::fan_out;
+use server::websockets::server::websocket_server;
+use server::websockets::hub::Hub;
+use server::services::health::MIGRATOR;
+use server::services::message_writer::MessageWriter;
+use server::services::search::apply_search_config;
+use tokio::net::TcpListener;
 use tokio::task;
+use std::sync::Arc;
+use std::net::SocketAddr;
+use std::time::Duration;
+use sqlx::PgPool;
 
 use rocket::fs::{NamedFile};
-use rocket::{get, routes};
-use std::path::{Path};
-
-#[catch(401)]
-fn unauthorized() -> Json<MessageOnlyResponse> {
-    Json(MessageOnlyResponse {
-        message: "User unauthorized!".to_string(),
-    })
-}
-
-
 
 #[get("/<file_name>")]
-async fn download_file(file_name: String) -> Option<NamedFile> {
-    let file_path = Path::new("./uploads").join(file_name);
+async fn download_file(config: &State<Config>, file_name: String) -> Option<NamedFile> {
+    let file_path = config.uploads.path_of(&file_name);
 
     if file_path.exists() {
         NamedFile::open(file_path).await.ok()
@@ -48,15 +45,89 @@ async fn download_file(file_name: String) -> Option<NamedFile> {
     }
 }
 
+/// Serves only `/metrics`, on an address scrapers can reach and clients can't.
+async fn metrics_server(address: SocketAddr, pool: PgPool, config: Config) {
+    let figment = rocket::Config::figment()
+        .merge(("address", address.ip()))
+        .merge(("port", address.port()));
+
+    let result = rocket::custom(figment)
+        .manage(pool)
+        .manage(config)
+        .mount("/", routes![routes::metrics::metrics])
+        .launch()
+        .await;
+
+    if let Err(error) = result {
+        tracing::error!(%error, "Metrics server failed");
+    }
+}
 
-#[launch]
-async fn rocket() -> Rocket<Build> {
+#[rocket::main]
+async fn main() {
     dotenv().ok();
 
-    let database_url = Env::database_url();
-    let pool = PgPool::connect(&database_url).await.expect("Failed to connect to the database");
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+
+    if let Err(error) = telemetry::init(&config.logging) {
+        eprintln!("Failed to set up logging: {}", error);
+        std::process::exit(1);
+    }
+    tracing::info!(?config, "Loaded configuration");
+
+    let pool = connect_database(&config).await;
+
+    // `server migrate` лише оновлює схему бази даних, не запускаючи сервер
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        tracing::info!("Database migrations applied");
+        telemetry::shutdown().await;
+        return;
+    }
+
+    let app = rocket(config, pool).await;
+
+    let hub = app.state::<Arc<Hub>>().cloned().expect("Hub is managed");
+    let lifecycle = app.state::<Arc<Lifecycle>>().cloned().expect("Lifecycle is managed");
+    let deadline = app.state::<Config>().map(|config| config.shutdown.deadline_seconds).expect("Config is managed");
+
+    if let Err(error) = app.launch().await {
+        tracing::error!(%error, "Server failed");
+    }
 
-    let allowed_origins = AllowedOrigins::some_exact(&[Env::client_url()]);
+    // HTTP-запити вже завершені; WebSocket-з'єднання отримують залишок того самого терміну
+    lifecycle.finish_shutdown(&hub, Duration::from_secs(deadline.into())).await;
+    telemetry::shutdown().await;
+}
+
+/// Connects to the database and brings its schema up to date.
+async fn connect_database(config: &Config) -> PgPool {
+    let pool = PgPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .acquire_timeout(Duration::from_secs(config.database.acquire_timeout_seconds))
+        .connect(config.database.url.expose())
+        .await
+        .expect("Failed to connect to the database");
+
+    // Застосовує нові міграції й перевіряє, що вже застосовані не змінилися
+    MIGRATOR
+        .run(&pool)
+        .await
+        .expect("Failed to apply database migrations");
+
+    pool
+}
+
+async fn rocket(config: Config, pool: PgPool) -> Rocket<Build> {
+    apply_search_config(&pool, &config.search.text_search_config)
+        .await
+        .expect("Failed to apply the search configuration");
+
+    std::fs::create_dir_all(&config.uploads.directory).expect("Failed to create the upload directory");
+
+    let allowed_origins = AllowedOrigins::some_exact(&config.cors.allowed_origins);
     let cors = CorsOptions {
         allowed_origins,
         allowed_methods: vec!["POST", "GET", "OPTIONS", "PUT", "DELETE", "PATCH"]
@@ -70,13 +141,75 @@ async fn rocket() -> Rocket<Build> {
         .to_cors()
         .expect("Failed to create CORS");
 
-    task::spawn(websocket_server(pool.clone()));
+    // Порт WebSocket прив'язується тут, щоб зайнятий порт зупиняв запуск, а не лише фонову задачу
+    let listener = TcpListener::bind(config.websocket.bind_address)
+        .await
+        .expect("Failed to bind WebSocket server");
 
-    rocket::build()
+    let hub = Arc::new(Hub::new());
+
+    // Трансляції доходять і до сокетів, підключених до інших екземплярів сервера
+    let backend = fan_out::connect(&config.fan_out, &pool)
+        .await
+        .expect("Failed to connect to the fan-out backend");
+    if let Some(backend) = backend {
+        fan_out::start(backend, hub.clone());
+    }
+
+    let lifecycle = Arc::new(Lifecycle::new());
+    let writer = MessageWriter::start(pool.clone(), &config.persistence);
+    task::spawn(websocket_server(listener, pool.clone(), hub.clone(), Arc::new(config.clone()), lifecycle.clone(), writer));
+
+    // Окрема адреса для /metrics, якщо задана; інакше він на порту API і вимагає токен
+    let metrics_on_api_port = config.metrics.enabled && config.metrics.bind_address.is_none();
+    if let (true, Some(address)) = (config.metrics.enabled, config.metrics.bind_address) {
+        task::spawn(metrics_server(address, pool.clone(), config.clone()));
+    }
+
+    // Після SIGTERM Rocket перестає приймати з'єднання й чекає на відкриті запити до кінця терміну
+    let figment = rocket::Config::figment()
+        .merge(("shutdown.grace", config.shutdown.deadline_seconds))
+        .merge(("shutdown.mercy", 0));
+
+    let mut app = rocket::custom(figment)
         .manage(pool)
+        .manage(hub)
+        .manage(lifecycle)
+        .manage(config)
         .attach(cors)
-        .mount("/api/users", routes![register, login, logout, get_users, get_user])
-        .mount("/api/chats", routes![create_chat, get_chats, get_chat_by_id, get_chat_messages])
+        .attach(RequestIdFairing)
+        .attach(RequestSpanFairing)
+        .attach(MetricsFairing)
+        .attach(AdHoc::on_shutdown("WebSocket connections", |rocket| Box::pin(async move {
+            if let (Some(hub), Some(lifecycle)) = (rocket.state::<Arc<Hub>>(), rocket.state::<Arc<Lifecycle>>()) {
+                lifecycle.begin_shutdown(hub);
+            }
+        })))
+        .mount("/", routes![health::healthz, health::readyz])
         .mount("/api/files", routes![download_file])
-        .register("/", catchers![unauthorized])
+        .mount("/api", routes![docs::asyncapi])
+        .mount("/api/docs", make_swagger_ui(&docs::swagger_ui_config()))
+        .register("/", catchers![bad_request, unauthorized, forbidden, not_found, unprocessable_entity, too_many_requests, internal_error]);
+
+    if metrics_on_api_port {
+        app = app.mount("/", routes![routes::metrics::metrics]);
+    }
+
+    // Маршрути монтуються разом з їхнім описом, який віддається як /api/openapi.json
+    let openapi_settings = OpenApiSettings::default();
+    mount_endpoints_and_merged_docs! {
+        app, "/api".to_string(), openapi_settings,
+        "/" => (Vec::new(), docs::openapi_info()),
+        "/users" => openapi_get_routes_spec![openapi_settings: auth::register, auth::login, auth::logout, users::get_users, users::get_user, users::get_presence, users::get_privacy_settings, users::update_privacy_settings, users::get_profile, users::update_profile, users::upload_avatar, users::get_blocked_users, users::block, users::unblock],
+        "/chats" => openapi_get_routes_spec![openapi_settings: chats::create_chat, chats::get_chats, chats::get_chat_by_id, chats::get_chat_messages, chats::mark_chat_read, chats::get_chat_requests, chats::respond_to_chat_request, chats::update_chat_settings, chats::reorder_pinned_chats],
+        "/chats" => openapi_get_routes_spec![openapi_settings: groups::create_group, groups::update_chat, groups::upload_chat_avatar, groups::get_chat_members, groups::add_chat_member, groups::remove_chat_member, groups::update_member_role, groups::transfer_ownership],
+        "/chats" => openapi_get_routes_spec![openapi_settings: invites::create_invite, invites::get_invites, invites::revoke_invite, invites::get_invite_uses, invites::review_join_request],
+        "/chats" => openapi_get_routes_spec![openapi_settings: messages::edit_message, messages::delete_message, messages::get_message_history, messages::get_thread, messages::add_reaction, messages::remove_reaction, messages::pin_message, messages::unpin_message],
+        "/contacts" => openapi_get_routes_spec![openapi_settings: contacts::get_contacts, contacts::create_contact, contacts::delete_contact],
+        "/invites" => openapi_get_routes_spec![openapi_settings: invites::preview_invite, invites::join_by_invite],
+        "/folders" => openapi_get_routes_spec![openapi_settings: folders::get_folders, folders::create_folder, folders::update_folder, folders::delete_folder, folders::reorder_folders],
+        "/search" => openapi_get_routes_spec![openapi_settings: search::search],
+    };
+
+    app
 }
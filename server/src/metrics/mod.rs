@@ -0,0 +1,180 @@
+use std::sync::LazyLock;
+use prometheus::core::Collector;
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use sqlx::PgPool;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// The metrics of this process. Updated where things happen, except the pool gauges,
+/// which are read when `/metrics` is scraped.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+const BATCH_BUCKETS: [f64; 8] = [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0];
+const FAN_OUT_BUCKETS: [f64; 10] = [0.0, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1000.0];
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub websocket_connections: IntGauge,
+    pub chat_messages: IntCounter,
+    pub message_write_queue: IntGauge,
+    pub message_batch_size: Histogram,
+    pub message_commit_duration: Histogram,
+    pub broadcast_recipients: Histogram,
+    pub outbound_queue_depth: IntGauge,
+    pub db_pool_connections: IntGaugeVec,
+    pub db_query_duration: HistogramVec,
+    pub upload_bytes: IntCounterVec,
+    pub auth_failures: IntCounterVec,
+}
+
+fn register<C: Collector + Clone + 'static>(registry: &Registry, collector: C) -> C {
+    registry.register(Box::new(collector.clone())).expect("Metric registered twice");
+    collector
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        Metrics {
+            http_requests: register(&registry, IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+                &["route", "method", "status"],
+            ).unwrap()),
+            http_request_duration: register(&registry, HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Time to handle an HTTP request").buckets(LATENCY_BUCKETS.to_vec()),
+                &["route", "method"],
+            ).unwrap()),
+            websocket_connections: register(&registry, IntGauge::new(
+                "websocket_connections", "Open WebSocket connections",
+            ).unwrap()),
+            chat_messages: register(&registry, IntCounter::new(
+                "chat_messages_total", "Chat messages sent over WebSockets",
+            ).unwrap()),
+            message_write_queue: register(&registry, IntGauge::new(
+                "chat_message_write_queue", "Chat messages waiting to be stored",
+            ).unwrap()),
+            message_batch_size: register(&registry, Histogram::with_opts(
+                HistogramOpts::new("chat_message_batch_size", "Chat messages stored in one transaction").buckets(BATCH_BUCKETS.to_vec()),
+            ).unwrap()),
+            message_commit_duration: register(&registry, Histogram::with_opts(
+                HistogramOpts::new("chat_message_commit_seconds", "Time from queueing a chat message until it is committed").buckets(LATENCY_BUCKETS.to_vec()),
+            ).unwrap()),
+            broadcast_recipients: register(&registry, Histogram::with_opts(
+                HistogramOpts::new("websocket_broadcast_recipients", "Sockets a broadcast frame was queued for").buckets(FAN_OUT_BUCKETS.to_vec()),
+            ).unwrap()),
+            outbound_queue_depth: register(&registry, IntGauge::new(
+                "websocket_outbound_queue_depth", "Frames queued for all sockets and not yet written",
+            ).unwrap()),
+            db_pool_connections: register(&registry, IntGaugeVec::new(
+                Opts::new("db_pool_connections", "Database pool connections by state"),
+                &["state"],
+            ).unwrap()),
+            db_query_duration: register(&registry, HistogramVec::new(
+                HistogramOpts::new("db_query_duration_seconds", "Time to run a database statement").buckets(LATENCY_BUCKETS.to_vec()),
+                &["statement"],
+            ).unwrap()),
+            upload_bytes: register(&registry, IntCounterVec::new(
+                Opts::new("upload_bytes_total", "Bytes of uploaded files and avatars"),
+                &["kind"],
+            ).unwrap()),
+            auth_failures: register(&registry, IntCounterVec::new(
+                Opts::new("auth_failures_total", "Rejected authentication attempts by reason"),
+                &["reason"],
+            ).unwrap()),
+            registry,
+        }
+    }
+
+    pub fn update_pool(&self, pool: &PgPool, max_connections: u32) {
+        let size = pool.size() as i64;
+        let idle = pool.num_idle() as i64;
+
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections.with_label_values(&["in_use"]).set(size - idle);
+        self.db_pool_connections.with_label_values(&["max"]).set(max_connections as i64);
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Times database statements from the events sqlx logs for each of them.
+/// sqlx only emits them at debug level, so give this layer its own filter.
+pub struct QueryMetricsLayer;
+
+#[derive(Default)]
+struct QueryEvent {
+    statement: Option<&'static str>,
+    elapsed_secs: Option<f64>,
+}
+
+impl Visit for QueryEvent {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "summary" {
+            self.statement = Some(statement_kind(value));
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// The leading keyword of a statement, so the label stays low-cardinality.
+fn statement_kind(summary: &str) -> &'static str {
+    let keyword = summary.split_whitespace().next().unwrap_or_default();
+
+    ["SELECT", "INSERT", "UPDATE", "DELETE", "WITH"]
+        .into_iter()
+        .find(|kind| keyword.eq_ignore_ascii_case(kind))
+        .unwrap_or("OTHER")
+}
+
+impl<S: Subscriber> Layer<S> for QueryMetricsLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if event.metadata().target() != "sqlx::query" {
+            return;
+        }
+
+        let mut query = QueryEvent::default();
+        event.record(&mut query);
+
+        if let (Some(statement), Some(elapsed_secs)) = (query.statement, query.elapsed_secs) {
+            METRICS.db_query_duration.with_label_values(&[statement]).observe(elapsed_secs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_are_labelled_by_their_first_keyword() {
+        assert_eq!(statement_kind("SELECT id FROM users WHERE …"), "SELECT");
+        assert_eq!(statement_kind("  insert INTO messages (chat_id, …"), "INSERT");
+        assert_eq!(statement_kind("UPDATE chats SET …"), "UPDATE");
+        assert_eq!(statement_kind("delete FROM messages …"), "DELETE");
+        assert_eq!(statement_kind("WITH rows AS (…"), "WITH");
+    }
+
+    #[test]
+    fn other_statements_share_one_label() {
+        for summary in ["", "BEGIN", "COMMIT", "LISTEN chat_fan_out", "SELECTED", "/* SELECT */ SELECT 1"] {
+            assert_eq!(statement_kind(summary), "OTHER", "{:?}", summary);
+        }
+    }
+}
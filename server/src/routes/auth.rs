@@ -2,13 +2,15 @@ use sqlx::PgPool;
 use jsonwebtoken::{encode, Header, EncodingKey};
 use bcrypt::{hash, verify};
 use rocket::State;
-use rocket::http::Status;
 use rocket::serde::json::Json;
-use crate::environment::Env;
+use rocket_okapi::openapi;
+use crate::config::Config;
+use crate::errors::{ApiError, ErrorContext};
 use crate::utils::time::{get_current_timestamp};
 use crate::utils::validators::{is_email};
-use crate::constants::common::{BCRYPT_COST, JWT_EXPIRATION_SECONDS};
+use crate::constants::common::BCRYPT_COST;
 use crate::guards::auth::AuthGuard;
+use crate::metrics::METRICS;
 use crate::dtos::responses::{MessageOnlyResponse, LoginResponse};
 use crate::dtos::user::{User, UserWithoutPassword};
 use crate::dtos::others::{Claims};
@@ -16,116 +18,84 @@ use crate::dtos::others::{Claims};
 
 
 
+#[openapi(tag = "Auth")]
 #[post("/register", format = "json", data = "<user>")]
-pub async fn register(user: Json<User>, pool: &State<PgPool>) -> Result<Json<MessageOnlyResponse>, (Status, Json<MessageOnlyResponse>)> {
+pub async fn register(user: Json<User>, pool: &State<PgPool>) -> Result<Json<MessageOnlyResponse>, ApiError> {
     if !is_email(&user.email) {
-        return Err((
-            Status::BadRequest,
-            Json(MessageOnlyResponse {
-                message: "Invalid email address!".to_string(),
-            }),
-        ));
+        return Err(ApiError::invalid_field("email", "Invalid email address!"));
     }
 
     if user.password.len() < 8 {
-        return Err((
-            Status::BadRequest,
-            Json(MessageOnlyResponse {
-                message: "Password must be at least 8 characters long!".to_string(),
-            }),
-        ));
+        return Err(ApiError::invalid_field("password", "Password must be at least 8 characters long!"));
     }
 
-    if let Ok(Some(_)) = sqlx::query!("SELECT id FROM users WHERE email = $1", user.email)
+    let existing = sqlx::query!("SELECT id FROM users WHERE email = $1", user.email)
         .fetch_optional(pool.inner())
         .await
-    {
-        return Err((
-            Status::Conflict,
-            Json(MessageOnlyResponse {
-                message: "User with this email already exists!".to_string(),
-            }),
-        ));
+        .context("Failed to register user!")?;
+
+    if existing.is_some() {
+        return Err(ApiError::Conflict("User with this email already exists!".to_string()));
     }
 
     let hashed_password = hash(&user.password, BCRYPT_COST)
         .expect("Hashing password failed. Ensure BCRYPT_COST is within valid range.");
 
 
-    let result = sqlx::query!(
+    sqlx::query!(
         "INSERT INTO users (email, password) VALUES ($1, $2)",
         user.email,
         hashed_password
     )
         .execute(pool.inner())
-        .await;
-
-    match result {
-        Ok(_) => Ok(Json(MessageOnlyResponse {
-            message: "User registered successfully!".to_string(),
-        })),
-        Err(_) => Err((
-            Status::InternalServerError,
-            Json(MessageOnlyResponse {
-                message: "Failed to register user!".to_string(),
-            }),
-        )),
-    }
+        .await
+        .context("Failed to register user!")?;
+
+    Ok(Json(MessageOnlyResponse {
+        message: "User registered successfully!".to_string(),
+    }))
 }
 
+#[openapi(tag = "Auth")]
 #[post("/login", format = "json", data = "<user>")]
-pub async fn login(user: Json<User>, pool: &State<PgPool>) -> Result<Json<LoginResponse>, (Status, Json<LoginResponse>)> {
-    match sqlx::query!("SELECT id, email, password FROM users WHERE email = $1", user.email)
-        .fetch_one(pool.inner())
+pub async fn login(user: Json<User>, pool: &State<PgPool>, config: &State<Config>) -> Result<Json<LoginResponse>, ApiError> {
+    let invalid_credentials = || {
+        METRICS.auth_failures.with_label_values(&["invalid_credentials"]).inc();
+        ApiError::Unauthorized("Invalid email or password!".to_string())
+    };
+
+    let record = sqlx::query!("SELECT id, email, password FROM users WHERE email = $1", user.email)
+        .fetch_optional(pool.inner())
         .await
-    {
-        Ok(record) => {
-            match verify(&user.password, &record.password) {
-                Ok(valid) if valid => {
-                    let claims = Claims {
-                        sub: user.email.clone(),
-                        exp: get_current_timestamp() + JWT_EXPIRATION_SECONDS,
-                    };
-
-                    let jwt_secret = Env::jwt_secret();
-                    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_bytes())).unwrap();
-
-                    let user_data = UserWithoutPassword {
-                        id: Some(record.id),
-                        email: record.email.clone(),
-                    };
-
-                    Ok(Json(LoginResponse {
-                        message: format!("User {} logged in successfully!", user.email),
-                        token: Some(token),
-                        user: Some(user_data),
-                    }))
-                }
-                _ => {
-                    Err((
-                        Status::Unauthorized,
-                        Json(LoginResponse {
-                            message: "Invalid email or password!".to_string(),
-                            token: None,
-                            user: None,
-                        }),
-                    ))
-                }
-            }
-        },
-        Err(_) => {
-            Err((
-                Status::Unauthorized,
-                Json(LoginResponse {
-                    message: "Invalid email or password!".to_string(),
-                    token: None,
-                    user: None,
-                }),
-            ))
-        },
+        .context("Failed to log in")?
+        .ok_or_else(invalid_credentials)?;
+
+    if !verify(&user.password, &record.password).unwrap_or(false) {
+        return Err(invalid_credentials());
     }
+
+    let claims = Claims {
+        sub: user.email.clone(),
+        exp: get_current_timestamp() + config.auth.token_lifetime_seconds,
+    };
+
+    let jwt_secret = config.auth.jwt_secret.expose();
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_bytes()))
+        .map_err(|_| ApiError::Internal("Failed to log in".to_string()))?;
+
+    let user_data = UserWithoutPassword {
+        id: Some(record.id),
+        email: record.email.clone(),
+    };
+
+    Ok(Json(LoginResponse {
+        message: format!("User {} logged in successfully!", user.email),
+        token: Some(token),
+        user: Some(user_data),
+    }))
 }
 
+#[openapi(tag = "Auth")]
 #[post("/logout")]
 pub fn logout(_auth: AuthGuard) -> Json<MessageOnlyResponse> {
     Json(MessageOnlyResponse {
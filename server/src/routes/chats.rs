@@ -1,56 +1,134 @@
 use rocket::State;
-use rocket::serde::json::{Json, Value};
-use sqlx::PgPool;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::{PgConnection, PgPool};
+use sqlx::types::chrono::Utc;
+use crate::errors::{ApiError, ErrorContext};
 use crate::guards::auth::AuthGuard;
-use crate::dtos::chat::{CreateChatRequest, ChatResponse, MessageResponse};
-use rocket::http::Status;
-use rocket::response::status;
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::dtos::chat::{ChatRequestAction, ChatRequestStatus, CreateChatRequest, ChatResponse, MessageResponse, MarkReadRequest, ReorderPinnedChatsRequest, RespondToChatRequest, UpdateChatSettingsRequest};
+use crate::constants::common::{MAX_PINNED_CHATS, MIN_USER_SEARCH_LENGTH};
+use crate::services::blocks::{block_user, can_start_direct_chat};
+use crate::services::chats::{fetch_chats, is_chat_member, notify_members, ChatQuery};
+use crate::services::contacts::{add_contact, is_contact};
+use crate::services::folders::folder_exists;
+use crate::services::messages::{fetch_messages, MessageQuery};
+use crate::services::receipts::{mark_delivered, mark_read};
+use crate::services::users::{is_discoverable_by, search_users};
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+use std::sync::Arc;
 
+/// Finds a chat participant by id or email, honouring the directory privacy rules.
+async fn resolve_participant(
+    pool: &PgPool,
+    viewer_id: i32,
+    email: Option<&str>,
+    user_id: Option<i32>,
+) -> Result<(i32, String), ApiError> {
+    let not_found = |identifier: String| ApiError::NotFound(format!("User '{}' not found", identifier));
+    let lookup_failed = "Failed to look up users";
+
+    let user = match (user_id, email) {
+        (Some(user_id), _) => sqlx::query!("SELECT id, email FROM users WHERE id = $1", user_id)
+            .fetch_optional(pool)
+            .await
+            .context(lookup_failed)?
+            .map(|user| (user.id, user.email)),
+        (None, Some(email)) => sqlx::query!("SELECT id, email FROM users WHERE email = $1", email)
+            .fetch_optional(pool)
+            .await
+            .context(lookup_failed)?
+            .map(|user| (user.id, user.email)),
+        (None, None) => {
+            return Err(ApiError::BadRequest("Each participant needs an email or a user id".to_string()));
+        }
+    };
+
+    let identifier = user_id.map(|id| id.to_string()).or(email.map(str::to_string)).unwrap_or_default();
+
+    match user {
+        Some((id, email)) => {
+            if is_discoverable_by(pool, viewer_id, id).await.context(lookup_failed)? {
+                Ok((id, email))
+            } else {
+                Err(not_found(identifier))
+            }
+        }
+        None => {
+            // Неточний email шукаємо так само, як у довіднику користувачів, але лише однозначний збіг
+            let query = email.unwrap_or_default();
+            if query.chars().count() < MIN_USER_SEARCH_LENGTH {
+                return Err(not_found(identifier));
+            }
+
+            let mut matches = search_users(pool, viewer_id, query, 0, 2).await.context(lookup_failed)?;
+            match (matches.pop(), matches.is_empty()) {
+                (Some(user), true) => {
+                    let email = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", user.id)
+                        .fetch_one(pool)
+                        .await
+                        .context(lookup_failed)?;
+
+                    Ok((user.id, email))
+                }
+                _ => Err(not_found(identifier)),
+            }
+        }
+    }
+}
+
+#[openapi(tag = "Chats")]
 #[post("/", format = "json", data = "<chat_request>")]
 pub async fn create_chat(
-    _auth: AuthGuard,
+    auth: AuthGuard,
     pool: &State<PgPool>,
     chat_request: Json<CreateChatRequest>,
-) -> Result<Json<ChatResponse>, status::Custom<Json<Value>>> {
-    let user1_email = &chat_request.user1_email;
-    let user2_email = &chat_request.user2_email;
-
-    // Перевірка: чи однакові email
-    if user1_email == user2_email {
-        let error_message = serde_json::json!({
-            "message": "User emails must be different"
-        });
-        return Err(status::Custom(Status::BadRequest, Json(error_message)));
-    }
-
-    // Отримати ID користувачів за email
-    let user1_id = sqlx::query!(
-        "SELECT id FROM users WHERE email = $1",
-        user1_email
-    )
-        .fetch_one(pool.inner())
-        .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": format!("User with email '{}' not found", user1_email)
-        });
-            status::Custom(Status::NotFound, Json(error_message))
-        })?
-        .id;
-
-    let user2_id = sqlx::query!(
-        "SELECT id FROM users WHERE email = $1",
-        user2_email
-    )
-        .fetch_one(pool.inner())
+) -> Result<Json<ChatResponse>, ApiError> {
+    // Отримати ID користувачів за email або ID
+    let (user1_id, user1_email) = resolve_participant(
+        pool.inner(),
+        auth.user_id,
+        chat_request.user1_email.as_deref(),
+        chat_request.user1_id,
+    ).await?;
+
+    let (user2_id, user2_email) = resolve_participant(
+        pool.inner(),
+        auth.user_id,
+        chat_request.user2_email.as_deref(),
+        chat_request.user2_id,
+    ).await?;
+
+    // Перевірка: чи це різні користувачі
+    if user1_id == user2_id {
+        return Err(ApiError::BadRequest("User emails must be different".to_string()));
+    }
+
+    // Перевірка: чи є поточний користувач одним з учасників
+    let other_id = if user1_id == auth.user_id {
+        user2_id
+    } else if user2_id == auth.user_id {
+        user1_id
+    } else {
+        return Err(ApiError::Forbidden("You can only create chats you take part in".to_string()));
+    };
+
+    // Перевірка: чи дозволяє співрозмовник починати з ним чат (блокування та налаштування приватності)
+    let allowed = can_start_direct_chat(pool.inner(), auth.user_id, other_id)
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": format!("User with email '{}' not found", user2_email)
-        });
-            status::Custom(Status::NotFound, Json(error_message))
-        })?
-        .id;
+        .context("Failed to check privacy settings")?;
+
+    if !allowed {
+        return Err(ApiError::Forbidden("This user does not accept new chats from you".to_string()));
+    }
+
+    // Чат від когось не з контактів потрапляє до співрозмовника як запит
+    let other_status = if is_contact(pool.inner(), other_id, auth.user_id).await.context("Failed to create chat")? {
+        ChatRequestStatus::Accepted
+    } else {
+        ChatRequestStatus::Pending
+    };
 
     // Перевірка: чи вже існує чат між цими користувачами
     let existing_chat = sqlx::query!(
@@ -63,186 +141,393 @@ pub async fn create_chat(
     )
         .fetch_optional(pool.inner())
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": "Failed to check for existing chat"
-        });
-            status::Custom(Status::InternalServerError, Json(error_message))
-        })?;
+        .context("Failed to check for existing chat")?;
 
     if existing_chat.is_some() {
-        let error_message = serde_json::json!({
-            "message": "Chat between these users already exists"
-        });
-        return Err(status::Custom(Status::Conflict, Json(error_message)));
+        return Err(ApiError::Conflict("Chat between these users already exists".to_string()));
     }
 
+    let mut tx = pool.begin().await.context("Failed to create chat")?;
+
     // Створення нового чату, зберігаючи email користувачів
     let chat = sqlx::query!(
         r#"
         INSERT INTO chats (user1_id, user2_id, user1_email, user2_email, created_at)
         VALUES ($1, $2, $3, $4, NOW())
-        RETURNING id, user1_id, user2_id, user1_email, user2_email, created_at
+        RETURNING id
         "#,
         user1_id,
         user2_id,
         user1_email,
         user2_email
     )
-        .fetch_one(pool.inner())
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to create chat")?;
+
+    sqlx::query!(
+        "INSERT INTO chat_members (chat_id, user_id, request_status) VALUES ($1, $2, 'accepted'), ($1, $3, $4)",
+        chat.id,
+        auth.user_id,
+        other_id,
+        other_status as ChatRequestStatus
+    )
+        .execute(&mut *tx)
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": "Failed to create chat"
-        });
-            status::Custom(Status::InternalServerError, Json(error_message))
-        })?;
+        .context("Failed to create chat")?;
+
+    tx.commit().await.context("Failed to create chat")?;
 
     // Повернення чату у відповіді
-    Ok(Json(ChatResponse {
-        id: chat.id,
-        user1_id: chat.user1_id,
-        user2_id: chat.user2_id,
-        user1_email: chat.user1_email,
-        user2_email: chat.user2_email,
-        created_at: chat.created_at,
-    }))
+    get_chat_by_id(auth, pool, chat.id).await
 }
 
+#[openapi(tag = "Chats")]
 #[get("/<chat_id>/messages/all")]
 pub async fn get_chat_messages(
+    auth: AuthGuard,
     pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
     chat_id: i32,
-) -> Result<Json<Vec<MessageResponse>>, status::Custom<Json<Value>>> {
-    let messages = sqlx::query!(
-        "SELECT id, chat_id, user_id, content, created_at, file_path, message_type FROM messages WHERE chat_id = $1 ORDER BY created_at ASC",
-        chat_id
-    )
-        .fetch_all(pool.inner())
+) -> Result<Json<Vec<MessageResponse>>, ApiError> {
+    let is_member = is_chat_member(pool.inner(), chat_id, auth.user_id)
+        .await
+        .context("Failed to fetch messages")?;
+
+    if !is_member {
+        return Err(ApiError::Forbidden("You are not a member of this chat".to_string()));
+    }
+
+    let messages = fetch_messages(pool.inner(), auth.user_id, chat_id, MessageQuery::default())
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({ "message": "Failed to fetch messages" });
-            status::Custom(Status::InternalServerError, Json(error_message))
-        })?;
-
-    let response = messages.into_iter().map(|msg| MessageResponse {
-        id: msg.id,
-        chat_id: msg.chat_id,
-        user_id: msg.user_id,
-        content: msg.content,
-        created_at: msg.created_at,
-        file_path: msg.file_path,
-        message_type: msg.message_type
-    }).collect::<Vec<MessageResponse>>();
-
-    Ok(Json(response))
+        .context("Failed to fetch messages")?;
+
+    // Отримані через REST повідомлення вважаються доставленими
+    if let Some(latest) = messages.iter().map(|msg| msg.id).max() {
+        if let Err(e) = mark_delivered(pool.inner(), hub.inner(), chat_id, auth.user_id, latest).await {
+            tracing::error!(error = %e, chat_id, "Failed to update delivery cursor");
+        }
+    }
+
+    Ok(Json(messages))
+}
+
+#[openapi(tag = "Chats")]
+#[put("/<chat_id>/read", format = "json", data = "<read_request>")]
+pub async fn mark_chat_read(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    read_request: Json<MarkReadRequest>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    let is_member = is_chat_member(pool.inner(), chat_id, auth.user_id)
+        .await
+        .context("Failed to update read marker")?;
+
+    if !is_member {
+        return Err(ApiError::Forbidden("You are not a member of this chat".to_string()));
+    }
+
+    // Курсор рухається лише вперед, тож повторний або застарілий запит просто нічого не змінює
+    mark_read(pool.inner(), hub.inner(), chat_id, auth.user_id, read_request.message_id)
+        .await
+        .context("Failed to update read marker")?;
+
+    get_chat_by_id(auth, pool, chat_id).await
 }
 
-#[get("/user/<user_id>", format = "json")]
+#[openapi(tag = "Chats")]
+#[get("/user/<user_id>?<archived>&<folder_id>", format = "json")]
 pub async fn get_chats(
-    _auth: AuthGuard,
+    auth: AuthGuard,
     pool: &State<PgPool>,
     user_id: i32,
-) -> Result<Json<Vec<ChatResponse>>, status::Custom<Json<Value>>> {
-    // Запит з JOIN для отримання чатів разом з емейлами
-    let chats = sqlx::query!(
-        r#"
-        SELECT
-            c.id,
-            c.user1_id,
-            c.user2_id,
-            u1.email AS user1_email,
-            u2.email AS user2_email,
-            c.created_at
-        FROM chats c
-        JOIN users u1 ON c.user1_id = u1.id
-        JOIN users u2 ON c.user2_id = u2.id
-        WHERE c.user1_id = $1 OR c.user2_id = $1
-        ORDER BY c.created_at DESC
-        "#,
-        user_id
-    )
-        .fetch_all(pool.inner())
-        .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": "Failed to fetch chats"
-        });
-            status::Custom(Status::InternalServerError, Json(error_message))
-        })?;
+    archived: Option<bool>,
+    folder_id: Option<i32>,
+) -> Result<Json<Vec<ChatResponse>>, ApiError> {
+    // Лічильники непрочитаних персональні, тож чужий список чатів недоступний
+    if user_id != auth.user_id {
+        return Err(ApiError::Forbidden("You can only list your own chats".to_string()));
+    }
+
+    if let Some(folder_id) = folder_id {
+        let exists = folder_exists(pool.inner(), auth.user_id, folder_id).await.context("Failed to fetch chats")?;
 
-    if chats.is_empty() {
-        return Ok(Json(Vec::new()));
+        if !exists {
+            return Err(ApiError::NotFound(format!("Folder with id '{}' not found", folder_id)));
+        }
     }
 
-    // Формуємо відповіді з чатом і емейлами
-    let chat_responses: Vec<ChatResponse> = chats
-        .into_iter()
-        .map(|chat| ChatResponse {
-            id: chat.id,
-            user1_id: chat.user1_id,
-            user2_id: chat.user2_id,
-            user1_email: Some(chat.user1_email),  // емейл користувача 1
-            user2_email: Some(chat.user2_email),  // емейл користувача 2
-            created_at: chat.created_at,
-        })
-        .collect();
+    let query = ChatQuery { archived: archived.unwrap_or(false), folder_id, ..Default::default() };
+    let chat_responses = fetch_chats(pool.inner(), user_id, query)
+        .await
+        .context("Failed to fetch chats")?;
 
     Ok(Json(chat_responses))
 }
 
 
+#[openapi(tag = "Chats")]
 #[get("/<chat_id>", format = "json")]
 pub async fn get_chat_by_id(
-    _auth: AuthGuard,   // Авторизаційний гвард для перевірки доступу
+    auth: AuthGuard,    // Авторизаційний гвард для перевірки доступу
     pool: &State<PgPool>, // Доступ до пулу з'єднань з базою даних
     chat_id: i32,       // Ідентифікатор чату
-) -> Result<Json<ChatResponse>, status::Custom<Json<Value>>> {
+) -> Result<Json<ChatResponse>, ApiError> {
     // Запит для отримання чату за його id
-    let chat = sqlx::query!(
-        r#"
-        SELECT
-            c.id,
-            c.user1_id,
-            c.user2_id,
-            u1.email AS user1_email,
-            u2.email AS user2_email,
-            c.created_at
-        FROM chats c
-        JOIN users u1 ON c.user1_id = u1.id
-        JOIN users u2 ON c.user2_id = u2.id
-        WHERE c.id = $1
-        "#,
-        chat_id
-    )
-        .fetch_optional(pool.inner()) // Використовуємо `fetch_optional` для отримання або відсутності результатів
+    let chat = fetch_chats(pool.inner(), auth.user_id, ChatQuery { chat_id: Some(chat_id), ..Default::default() })
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-                "message": "Failed to fetch chat"
-            });
-            status::Custom(Status::InternalServerError, Json(error_message))
-        })?;
+        .context("Failed to fetch chat")?
+        .pop();
 
     match chat {
         Some(chat) => {
             // Якщо чат знайдений, повертаємо його як відповідь
-            Ok(Json(ChatResponse {
-                id: chat.id,
-                user1_id: chat.user1_id,
-                user2_id: chat.user2_id,
-                user1_email: Some(chat.user1_email),  // емейл користувача 1
-                user2_email: Some(chat.user2_email),  // емейл користувача 2
-                created_at: chat.created_at,
-            }))
+            Ok(Json(chat))
         }
         None => {
             // Якщо чат не знайдений, повертаємо помилку
-            let error_message = serde_json::json!({
-                "message": format!("Chat with id '{}' not found", chat_id)
-            });
-            Err(status::Custom(Status::NotFound, Json(error_message)))
+            Err(ApiError::NotFound(format!("Chat with id '{}' not found", chat_id)))
         }
     }
 }
 
+#[openapi(tag = "Chats")]
+#[get("/requests", format = "json")]
+pub async fn get_chat_requests(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<ChatResponse>>, ApiError> {
+    let requests = fetch_chats(pool.inner(), auth.user_id, ChatQuery { requests: true, ..Default::default() })
+        .await
+        .context("Failed to fetch chat requests")?;
+
+    Ok(Json(requests))
+}
+
+#[openapi(tag = "Chats")]
+#[put("/<chat_id>/request", format = "json", data = "<response>")]
+pub async fn respond_to_chat_request(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    response: Json<RespondToChatRequest>,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    let failed = "Failed to respond to chat request";
+
+    let request_status = match response.action {
+        ChatRequestAction::Accept => ChatRequestStatus::Accepted,
+        ChatRequestAction::Decline | ChatRequestAction::Block => ChatRequestStatus::Declined,
+    };
+
+    let mut tx = pool.begin().await.context(failed)?;
+
+    // Відповісти можна лише на власний запит, що ще очікує рішення
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chat_members
+        SET request_status = $1
+        WHERE chat_id = $2 AND user_id = $3 AND request_status = 'pending'
+        RETURNING chat_id
+        "#,
+        request_status as ChatRequestStatus,
+        chat_id,
+        auth.user_id
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(failed)?;
+
+    if updated.is_none() {
+        return Err(ApiError::NotFound("Chat request not found".to_string()));
+    }
+
+    let requesters = sqlx::query_scalar!(
+        "SELECT user_id FROM chat_members WHERE chat_id = $1 AND user_id <> $2",
+        chat_id,
+        auth.user_id
+    )
+        .fetch_all(&mut *tx)
+        .await
+        .context(failed)?;
+
+    // Прийнятий співрозмовник стає контактом, заблокований більше не зможе писати
+    for &requester in &requesters {
+        match response.action {
+            ChatRequestAction::Accept => {
+                add_contact(&mut *tx, auth.user_id, requester).await.context(failed)?;
+            }
+            ChatRequestAction::Block => {
+                block_user(&mut *tx, auth.user_id, requester).await.context(failed)?;
+            }
+            ChatRequestAction::Decline => {}
+        }
+    }
+
+    tx.commit().await.context(failed)?;
+
+    hub.forget_chat(chat_id);
+    if response.action == ChatRequestAction::Block {
+        hub.forget_member_chats(auth.user_id);
+    }
+
+    // Ініціатор дізнається про рішення; блокування він бачить як відхилений запит
+    let event = ServerEvent::ChatRequestAnswered { chat_id, user_id: auth.user_id, status: request_status };
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    let message = match response.action {
+        ChatRequestAction::Accept => "Chat request accepted!",
+        ChatRequestAction::Decline => "Chat request declined!",
+        ChatRequestAction::Block => "Chat request declined and user blocked!",
+    };
+
+    Ok(Json(MessageOnlyResponse {
+        message: message.to_string(),
+    }))
+}
+
+/// Locks every chat membership of the user until the transaction ends and returns the pinned chats.
+/// Pinning and reordering go through this lock, so the pin limit and positions stay consistent.
+async fn lock_pinned_chats(conn: &mut PgConnection, user_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    let memberships = sqlx::query!(
+        "SELECT chat_id, pinned_position FROM chat_members WHERE user_id = $1 FOR UPDATE",
+        user_id
+    )
+        .fetch_all(conn)
+        .await?;
+
+    Ok(memberships
+        .into_iter()
+        .filter(|membership| membership.pinned_position.is_some())
+        .map(|membership| membership.chat_id)
+        .collect())
+}
+
+#[openapi(tag = "Chats")]
+#[patch("/<chat_id>/settings", format = "json", data = "<settings>")]
+pub async fn update_chat_settings(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    settings: Json<UpdateChatSettingsRequest>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    let failed = "Failed to update chat settings";
+
+    if settings.archived == Some(true) && settings.pinned == Some(true) {
+        return Err(ApiError::BadRequest("Archived chats can't be pinned".to_string()));
+    }
+
+    if settings.muted_until.is_some() && settings.muted != Some(true) {
+        return Err(ApiError::invalid_field("muted_until", "muted_until requires muted to be set"));
+    }
+
+    if settings.muted_until.is_some_and(|muted_until| muted_until <= Utc::now().naive_utc()) {
+        return Err(ApiError::invalid_field("muted_until", "muted_until must be in the future"));
+    }
+
+    let mut tx = pool.begin().await.context(failed)?;
+
+    // Паралельні запити цього ж користувача чекають, доки ліміт перевірено й позицію видано
+    let pinned = lock_pinned_chats(&mut *tx, auth.user_id).await.context(failed)?;
+
+    if settings.pinned == Some(true) {
+        let count = pinned.iter().filter(|&&pinned_chat_id| pinned_chat_id != chat_id).count();
+
+        if count as i64 >= MAX_PINNED_CHATS {
+            return Err(ApiError::BadRequest(format!("You can pin at most {} chats", MAX_PINNED_CHATS)));
+        }
+    }
+
+    // Новий закріплений чат стає останнім серед закріплених
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chat_members
+        SET muted = COALESCE($1, muted),
+            muted_until = CASE WHEN $1::BOOLEAN IS NULL THEN muted_until WHEN $1 THEN $2 ELSE NULL END,
+            archived = CASE WHEN $4::BOOLEAN THEN FALSE ELSE COALESCE($3, archived) END,
+            pinned_position = CASE
+                WHEN $3::BOOLEAN OR NOT $4::BOOLEAN THEN NULL
+                WHEN $4 AND pinned_position IS NULL THEN (
+                    SELECT COALESCE(MAX(p.pinned_position), 0) + 1 FROM chat_members p WHERE p.user_id = $6
+                )
+                ELSE pinned_position
+            END,
+            favourite = COALESCE($5, favourite)
+        WHERE chat_id = $7 AND user_id = $6 AND request_status <> 'declined'
+        RETURNING chat_id
+        "#,
+        settings.muted,
+        settings.muted_until,
+        settings.archived,
+        settings.pinned,
+        settings.favourite,
+        auth.user_id,
+        chat_id
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(failed)?;
+
+    if updated.is_none() {
+        return Err(ApiError::NotFound(format!("Chat with id '{}' not found", chat_id)));
+    }
+
+    tx.commit().await.context(failed)?;
+
+    let user_id = auth.user_id;
+    let chat = get_chat_by_id(auth, pool, chat_id).await?;
+
+    // Налаштування синхронізуються між усіма пристроями користувача
+    let event = ServerEvent::ChatSettingsChanged { chat_id, settings: chat.settings.clone() };
+    hub.send_to_users(&[user_id], &event.to_message());
+
+    Ok(chat)
+}
+
+#[openapi(tag = "Chats")]
+#[put("/pinned", format = "json", data = "<order>")]
+pub async fn reorder_pinned_chats(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    order: Json<ReorderPinnedChatsRequest>,
+) -> Result<Json<Vec<ChatResponse>>, ApiError> {
+    let failed = "Failed to reorder pinned chats";
+
+    let mut tx = pool.begin().await.context(failed)?;
+    let mut expected = lock_pinned_chats(&mut *tx, auth.user_id).await.context(failed)?;
+
+    // Новий порядок має містити кожен закріплений чат рівно один раз
+    let mut given = order.chat_ids.clone();
+    expected.sort_unstable();
+    given.sort_unstable();
+
+    if expected != given {
+        return Err(ApiError::invalid_field("chat_ids", "The new order must list every pinned chat exactly once"));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE chat_members cm
+        SET pinned_position = p.position::INTEGER
+        FROM UNNEST($2::INTEGER[]) WITH ORDINALITY AS p(chat_id, position)
+        WHERE cm.user_id = $1 AND cm.chat_id = p.chat_id
+        "#,
+        auth.user_id,
+        &order.chat_ids
+    )
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    tx.commit().await.context(failed)?;
+
+    let event = ServerEvent::PinnedChatsReordered { chat_ids: order.chat_ids.clone() };
+    hub.send_to_users(&[auth.user_id], &event.to_message());
+
+    let user_id = auth.user_id;
+    get_chats(auth, pool, user_id, None, None).await
+}
@@ -0,0 +1,73 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::PgPool;
+use crate::errors::{ApiError, ErrorContext};
+use crate::guards::auth::AuthGuard;
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::dtos::user::UserSummary;
+use crate::services::contacts::{add_contact, fetch_contacts, remove_contact};
+use crate::services::users::is_discoverable_by;
+
+#[openapi(tag = "Contacts")]
+#[get("/", format = "json")]
+pub async fn get_contacts(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<UserSummary>>, ApiError> {
+    let contacts = fetch_contacts(pool.inner(), auth.user_id)
+        .await
+        .context("Failed to fetch contacts")?;
+
+    Ok(Json(contacts))
+}
+
+#[openapi(tag = "Contacts")]
+#[put("/<id>")]
+pub async fn create_contact(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    if id == auth.user_id {
+        return Err(ApiError::BadRequest("You can't add yourself to your contacts".to_string()));
+    }
+
+    // Додати можна лише того, кого видно в довіднику
+    let discoverable = is_discoverable_by(pool.inner(), auth.user_id, id)
+        .await
+        .context("Failed to add contact")?;
+
+    if !discoverable {
+        return Err(ApiError::NotFound("User not found".to_string()));
+    }
+
+    let mut conn = pool.acquire().await.context("Failed to add contact")?;
+    add_contact(&mut *conn, auth.user_id, id)
+        .await
+        .context("Failed to add contact")?;
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Contact added successfully!".to_string(),
+    }))
+}
+
+#[openapi(tag = "Contacts")]
+#[delete("/<id>")]
+pub async fn delete_contact(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    let removed = remove_contact(pool.inner(), auth.user_id, id)
+        .await
+        .context("Failed to remove contact")?;
+
+    if !removed {
+        return Err(ApiError::NotFound("User is not in your contacts".to_string()));
+    }
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Contact removed successfully!".to_string(),
+    }))
+}
@@ -0,0 +1,37 @@
+use rocket::State;
+use rocket::serde::json::{Json, Value};
+use rocket_okapi::okapi::openapi3::{Info, OpenApi};
+use rocket_okapi::swagger_ui::SwaggerUIConfig;
+use crate::config::Config;
+use crate::websockets::asyncapi::asyncapi_document;
+
+/// The parts of the OpenAPI document that don't come from the routes.
+pub fn openapi_info() -> OpenApi {
+    OpenApi {
+        openapi: OpenApi::default_version(),
+        info: Info {
+            title: "ChatApp API".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: Some(
+                "REST API of the chat server. Errors share one envelope with a machine-readable `code`. \
+                 The WebSocket protocol is described separately in `/api/asyncapi.json`."
+                    .to_string(),
+            ),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Swagger UI is mounted at `/api/docs` and reads the spec next to it.
+pub fn swagger_ui_config() -> SwaggerUIConfig {
+    SwaggerUIConfig {
+        url: "../openapi.json".to_string(),
+        ..Default::default()
+    }
+}
+
+#[get("/asyncapi.json")]
+pub fn asyncapi(config: &State<Config>) -> Json<Value> {
+    Json(asyncapi_document(&config.websocket))
+}
@@ -0,0 +1,229 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::PgPool;
+use crate::guards::auth::AuthGuard;
+use crate::constants::common::{MAX_CHAT_FOLDERS, MAX_FOLDER_NAME_LENGTH};
+use crate::errors::{ApiError, ErrorContext};
+use crate::dtos::folder::{ChatFolderResponse, CreateChatFolderRequest, ReorderChatFoldersRequest, UpdateChatFolderRequest};
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::services::folders::{are_own_chats, fetch_folders, replace_folder_chats, replace_folder_contacts};
+
+fn internal_error(error: sqlx::Error) -> ApiError {
+    ApiError::database(error, "Failed to update chat folders")
+}
+
+fn folder_not_found(folder_id: i32) -> ApiError {
+    ApiError::NotFound(format!("Folder with id '{}' not found", folder_id))
+}
+
+fn validate_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_FOLDER_NAME_LENGTH {
+        return Err(ApiError::invalid_field("name", format!("Folder name must be 1 to {} characters long", MAX_FOLDER_NAME_LENGTH)));
+    }
+
+    Ok(name.to_string())
+}
+
+async fn validate_chats(pool: &PgPool, user_id: i32, chat_ids: &[i32]) -> Result<(), ApiError> {
+    if are_own_chats(pool, user_id, chat_ids).await.map_err(internal_error)? {
+        Ok(())
+    } else {
+        Err(ApiError::invalid_field("chat_ids", "Folders can only hold chats you are a member of"))
+    }
+}
+
+async fn fetch_folder(pool: &PgPool, user_id: i32, folder_id: i32) -> Result<Json<ChatFolderResponse>, ApiError> {
+    fetch_folders(pool, user_id, Some(folder_id))
+        .await
+        .map_err(internal_error)?
+        .pop()
+        .map(Json)
+        .ok_or_else(|| folder_not_found(folder_id))
+}
+
+#[openapi(tag = "Folders")]
+#[get("/", format = "json")]
+pub async fn get_folders(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<ChatFolderResponse>>, ApiError> {
+    let folders = fetch_folders(pool.inner(), auth.user_id, None)
+        .await
+        .context("Failed to fetch chat folders")?;
+
+    Ok(Json(folders))
+}
+
+#[openapi(tag = "Folders")]
+#[post("/", format = "json", data = "<folder>")]
+pub async fn create_folder(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    folder: Json<CreateChatFolderRequest>,
+) -> Result<Json<ChatFolderResponse>, ApiError> {
+    let name = validate_name(&folder.name)?;
+    validate_chats(pool.inner(), auth.user_id, &folder.chat_ids).await?;
+
+    let mut tx = pool.begin().await.map_err(internal_error)?;
+
+    let existing = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!", COALESCE(MAX(position), 0) AS "last_position!" FROM chat_folders WHERE user_id = $1"#,
+        auth.user_id
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+
+    if existing.count >= MAX_CHAT_FOLDERS {
+        return Err(ApiError::BadRequest(format!("You can have at most {} folders", MAX_CHAT_FOLDERS)));
+    }
+
+    // Нова тека додається в кінець списку
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO chat_folders (user_id, name, position, include_unread, include_groups, include_direct, exclude_muted)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        auth.user_id,
+        name,
+        existing.last_position + 1,
+        folder.include_unread,
+        folder.include_groups,
+        folder.include_direct,
+        folder.exclude_muted
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+
+    replace_folder_chats(&mut *tx, created.id, &folder.chat_ids).await.map_err(internal_error)?;
+    replace_folder_contacts(&mut *tx, created.id, &folder.contact_ids).await.map_err(internal_error)?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    fetch_folder(pool.inner(), auth.user_id, created.id).await
+}
+
+#[openapi(tag = "Folders")]
+#[patch("/<folder_id>", format = "json", data = "<folder>")]
+pub async fn update_folder(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    folder_id: i32,
+    folder: Json<UpdateChatFolderRequest>,
+) -> Result<Json<ChatFolderResponse>, ApiError> {
+    let name = folder.name.as_deref().map(validate_name).transpose()?;
+
+    if let Some(chat_ids) = &folder.chat_ids {
+        validate_chats(pool.inner(), auth.user_id, chat_ids).await?;
+    }
+
+    let mut tx = pool.begin().await.map_err(internal_error)?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chat_folders
+        SET name = COALESCE($1, name),
+            include_unread = COALESCE($2, include_unread),
+            include_groups = COALESCE($3, include_groups),
+            include_direct = COALESCE($4, include_direct),
+            exclude_muted = COALESCE($5, exclude_muted)
+        WHERE id = $6 AND user_id = $7
+        RETURNING id
+        "#,
+        name,
+        folder.include_unread,
+        folder.include_groups,
+        folder.include_direct,
+        folder.exclude_muted,
+        folder_id,
+        auth.user_id
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+
+    if updated.is_none() {
+        return Err(folder_not_found(folder_id));
+    }
+
+    if let Some(chat_ids) = &folder.chat_ids {
+        replace_folder_chats(&mut *tx, folder_id, chat_ids).await.map_err(internal_error)?;
+    }
+
+    if let Some(contact_ids) = &folder.contact_ids {
+        replace_folder_contacts(&mut *tx, folder_id, contact_ids).await.map_err(internal_error)?;
+    }
+
+    tx.commit().await.map_err(internal_error)?;
+
+    fetch_folder(pool.inner(), auth.user_id, folder_id).await
+}
+
+#[openapi(tag = "Folders")]
+#[delete("/<folder_id>")]
+pub async fn delete_folder(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    folder_id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    let deleted = sqlx::query!(
+        "DELETE FROM chat_folders WHERE id = $1 AND user_id = $2",
+        folder_id,
+        auth.user_id
+    )
+        .execute(pool.inner())
+        .await
+        .map_err(internal_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(folder_not_found(folder_id));
+    }
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Folder deleted successfully!".to_string(),
+    }))
+}
+
+#[openapi(tag = "Folders")]
+#[put("/order", format = "json", data = "<order>")]
+pub async fn reorder_folders(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    order: Json<ReorderChatFoldersRequest>,
+) -> Result<Json<Vec<ChatFolderResponse>>, ApiError> {
+    let folders = sqlx::query!("SELECT id FROM chat_folders WHERE user_id = $1", auth.user_id)
+        .fetch_all(pool.inner())
+        .await
+        .map_err(internal_error)?;
+
+    // Новий порядок має містити кожну теку рівно один раз
+    let mut expected: Vec<i32> = folders.into_iter().map(|folder| folder.id).collect();
+    let mut given = order.folder_ids.clone();
+    expected.sort_unstable();
+    given.sort_unstable();
+
+    if expected != given {
+        return Err(ApiError::invalid_field("folder_ids", "The new order must list every folder exactly once"));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE chat_folders f
+        SET position = o.position::INTEGER
+        FROM UNNEST($2::INTEGER[]) WITH ORDINALITY AS o(id, position)
+        WHERE f.user_id = $1 AND f.id = o.id
+        "#,
+        auth.user_id,
+        &order.folder_ids
+    )
+        .execute(pool.inner())
+        .await
+        .map_err(internal_error)?;
+
+    get_folders(auth, pool).await
+}
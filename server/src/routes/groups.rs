@@ -0,0 +1,486 @@
+use rocket::State;
+use rocket::data::Data;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::{PgConnection, PgPool};
+use crate::config::Config;
+use crate::constants::common::{MAX_CHAT_TITLE_LENGTH, MAX_GROUP_MEMBERS};
+use crate::guards::auth::AuthGuard;
+use crate::dtos::chat::{AddMemberRequest, ChatMemberResponse, ChatResponse, ChatRole, CreateGroupRequest, TransferOwnershipRequest, UpdateChatRequest, UpdateMemberRoleRequest};
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::errors::{ApiError, ErrorContext};
+use crate::routes::chats::get_chat_by_id;
+use crate::services::blocks::is_blocked_between;
+use crate::services::chats::{fetch_chat_members, lock_member_count, notify_members};
+use crate::services::permissions::{member_access, role_rank, ChatPermission, MemberAccess};
+use crate::services::users::is_discoverable_by;
+use crate::utils::images::{save_avatar, AvatarError};
+use crate::utils::time::get_current_timestamp;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+use std::sync::Arc;
+
+/// Checks that the user is a member of the chat and their role grants the permission.
+pub async fn require_permission(
+    pool: &PgPool,
+    chat_id: i32,
+    user_id: i32,
+    permission: ChatPermission,
+) -> Result<MemberAccess, ApiError> {
+    let access = member_access(pool, chat_id, user_id)
+        .await
+        .context("Failed to check chat permissions")?
+        .ok_or_else(|| ApiError::Forbidden("You are not a member of this chat".to_string()))?;
+
+    if !access.can(permission) {
+        return Err(ApiError::Forbidden("You don't have permission to do this in this chat".to_string()));
+    }
+
+    Ok(access)
+}
+
+fn validate_title(title: &str) -> Result<String, ApiError> {
+    let title = title.trim();
+
+    if title.is_empty() || title.chars().count() > MAX_CHAT_TITLE_LENGTH {
+        let message = format!("Chat title must be 1 to {} characters long", MAX_CHAT_TITLE_LENGTH);
+        return Err(ApiError::invalid_field("title", message));
+    }
+
+    Ok(title.to_string())
+}
+
+/// Only users the adder can find and who have no block between them can be added to a group.
+async fn validate_new_member(pool: &PgPool, adder_id: i32, user_id: i32) -> Result<(), ApiError> {
+    let discoverable = is_discoverable_by(pool, adder_id, user_id)
+        .await
+        .context("Failed to add members")?;
+
+    if !discoverable {
+        return Err(ApiError::NotFound(format!("User '{}' not found", user_id)));
+    }
+
+    let blocked = is_blocked_between(pool, adder_id, user_id)
+        .await
+        .context("Failed to add members")?;
+
+    if blocked {
+        return Err(ApiError::Forbidden(format!("User '{}' can't be added to this chat", user_id)));
+    }
+
+    Ok(())
+}
+
+/// Adds plain members to a group. Like a direct chat, the group is a request for those
+/// who don't have the adder in their contacts. Re-adding someone who declined it asks again.
+async fn insert_members(conn: &mut PgConnection, chat_id: i32, adder_id: i32, user_ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO chat_members (chat_id, user_id, role, request_status)
+        SELECT
+            $1,
+            u.id,
+            'member',
+            CASE
+                WHEN EXISTS (SELECT 1 FROM contacts c WHERE c.user_id = u.id AND c.contact_id = $2) THEN 'accepted'
+                ELSE 'pending'
+            END::chat_request_status
+        FROM users u
+        WHERE u.id = ANY($3)
+        ON CONFLICT (chat_id, user_id) DO UPDATE
+        SET role = EXCLUDED.role, request_status = EXCLUDED.request_status, joined_at = NOW()
+        WHERE chat_members.request_status = 'declined'
+        RETURNING user_id
+        "#,
+        chat_id,
+        adder_id,
+        user_ids
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(added.into_iter().map(|member| member.user_id).collect())
+}
+
+async fn target_role(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<ChatRole, ApiError> {
+    member_access(pool, chat_id, user_id)
+        .await
+        .context("Failed to fetch chat member")?
+        .map(|access| access.role)
+        .ok_or_else(|| ApiError::NotFound(format!("User '{}' is not a member of this chat", user_id)))
+}
+
+#[openapi(tag = "Groups")]
+#[post("/groups", format = "json", data = "<group>")]
+pub async fn create_group(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    group: Json<CreateGroupRequest>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    let title = validate_title(&group.title)?;
+
+    let mut member_ids: Vec<i32> = group.member_ids.iter().copied().filter(|id| *id != auth.user_id).collect();
+    member_ids.sort_unstable();
+    member_ids.dedup();
+
+    if member_ids.len() + 1 > MAX_GROUP_MEMBERS {
+        let message = format!("A group can have at most {} members", MAX_GROUP_MEMBERS);
+        return Err(ApiError::BadRequest(message));
+    }
+
+    for member_id in &member_ids {
+        validate_new_member(pool.inner(), auth.user_id, *member_id).await?;
+    }
+
+    let failed = "Failed to create group";
+    let mut tx = pool.begin().await.context(failed)?;
+
+    let chat = sqlx::query!(
+        "INSERT INTO chats (is_group, title, created_at) VALUES (TRUE, $1, NOW()) RETURNING id",
+        title
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .context(failed)?;
+
+    sqlx::query!(
+        "INSERT INTO chat_members (chat_id, user_id, role, request_status) VALUES ($1, $2, 'owner', 'accepted')",
+        chat.id,
+        auth.user_id
+    )
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    let added = insert_members(&mut *tx, chat.id, auth.user_id, &member_ids).await.context(failed)?;
+
+    tx.commit().await.context(failed)?;
+
+    // Одна подія на всю групу замість MemberAdded для кожного учасника
+    let mut members = added;
+    members.push(auth.user_id);
+    let event = ServerEvent::ChatCreated { chat_id: chat.id, created_by: auth.user_id };
+    hub.send_to_users(&members, &event.to_message());
+
+    get_chat_by_id(auth, pool, chat.id).await
+}
+
+#[openapi(tag = "Groups")]
+#[patch("/<chat_id>", format = "json", data = "<chat>")]
+pub async fn update_chat(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    chat: Json<UpdateChatRequest>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::EditChatInfo).await?;
+    let title = validate_title(&chat.title)?;
+
+    let updated = sqlx::query!(
+        "UPDATE chats SET title = $1 WHERE id = $2 RETURNING title, avatar_path",
+        title,
+        chat_id
+    )
+        .fetch_one(pool.inner())
+        .await
+        .context("Failed to update chat")?;
+
+    let event = ServerEvent::ChatUpdated {
+        chat_id,
+        title: updated.title,
+        avatar_url: updated.avatar_path.map(|path| format!("/api/files/{}", path)),
+    };
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    get_chat_by_id(auth, pool, chat_id).await
+}
+
+#[openapi(tag = "Groups")]
+#[put("/<chat_id>/avatar", data = "<data>")]
+pub async fn upload_chat_avatar(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    config: &State<Config>,
+    chat_id: i32,
+    data: Data<'_>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::EditChatInfo).await?;
+
+    let file_name = format!("chat_avatar_{}_{}.png", chat_id, get_current_timestamp());
+
+    match save_avatar(data, &config.uploads, &file_name).await {
+        Ok(()) => {}
+        Err(AvatarError::TooLarge) => return Err(ApiError::BadRequest("Avatar is too large".to_string())),
+        Err(AvatarError::UnsupportedFormat) => return Err(ApiError::BadRequest("Unsupported image format".to_string())),
+        Err(AvatarError::Io(_)) => return Err(ApiError::Internal("Failed to save avatar".to_string())),
+    }
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chats c
+        SET avatar_path = $1
+        FROM chats old
+        WHERE c.id = $2 AND old.id = c.id
+        RETURNING c.title, old.avatar_path AS previous_avatar_path
+        "#,
+        file_name,
+        chat_id
+    )
+        .fetch_one(pool.inner())
+        .await
+        .context("Failed to save avatar")?;
+
+    // Попередній аватар більше ніде не використовується
+    if let Some(previous) = updated.previous_avatar_path.filter(|previous| *previous != file_name) {
+        let _ = tokio::fs::remove_file(config.uploads.path_of(&previous)).await;
+    }
+
+    let event = ServerEvent::ChatUpdated {
+        chat_id,
+        title: updated.title,
+        avatar_url: Some(format!("/api/files/{}", file_name)),
+    };
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    get_chat_by_id(auth, pool, chat_id).await
+}
+
+#[openapi(tag = "Groups")]
+#[get("/<chat_id>/members", format = "json")]
+pub async fn get_chat_members(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+) -> Result<Json<Vec<ChatMemberResponse>>, ApiError> {
+    member_access(pool.inner(), chat_id, auth.user_id)
+        .await
+        .context("Failed to fetch chat members")?
+        .ok_or_else(|| ApiError::Forbidden("You are not a member of this chat".to_string()))?;
+
+    let members = fetch_chat_members(pool.inner(), chat_id)
+        .await
+        .context("Failed to fetch chat members")?;
+
+    Ok(Json(members))
+}
+
+#[openapi(tag = "Groups")]
+#[post("/<chat_id>/members", format = "json", data = "<member>")]
+pub async fn add_chat_member(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    member: Json<AddMemberRequest>,
+) -> Result<Json<Vec<ChatMemberResponse>>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::AddMembers).await?;
+    validate_new_member(pool.inner(), auth.user_id, member.user_id).await?;
+
+    let failed = "Failed to add member";
+
+    let mut tx = pool.begin().await.context(failed)?;
+
+    if lock_member_count(&mut *tx, chat_id).await.context(failed)? >= MAX_GROUP_MEMBERS as i64 {
+        let message = format!("A group can have at most {} members", MAX_GROUP_MEMBERS);
+        return Err(ApiError::BadRequest(message));
+    }
+
+    let added = insert_members(&mut *tx, chat_id, auth.user_id, &[member.user_id]).await.context(failed)?;
+
+    if added.is_empty() {
+        return Err(ApiError::Conflict("User is already a member of this chat".to_string()));
+    }
+
+    tx.commit().await.context(failed)?;
+
+    hub.forget_chat(chat_id);
+
+    let event = ServerEvent::MemberAdded { chat_id, user_id: member.user_id, role: ChatRole::Member };
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    let members = fetch_chat_members(pool.inner(), chat_id).await.context(failed)?;
+
+    Ok(Json(members))
+}
+
+/// Removes a member, or lets the caller leave the group when `user_id` is their own.
+#[openapi(tag = "Groups")]
+#[delete("/<chat_id>/members/<user_id>")]
+pub async fn remove_chat_member(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    user_id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    if user_id == auth.user_id {
+        let access = member_access(pool.inner(), chat_id, auth.user_id)
+            .await
+            .context("Failed to leave chat")?
+            .ok_or_else(|| ApiError::Forbidden("You are not a member of this chat".to_string()))?;
+
+        if !access.is_group {
+            return Err(ApiError::BadRequest("Direct chats can't be left".to_string()));
+        }
+
+        if access.role == ChatRole::Owner {
+            return Err(ApiError::Conflict("Transfer the ownership before leaving the group".to_string()));
+        }
+    } else {
+        let access = require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::RemoveMembers).await?;
+        let role = target_role(pool.inner(), chat_id, user_id).await?;
+
+        // Видаляти можна лише тих, хто має нижчу роль
+        if role_rank(role) >= role_rank(access.role) {
+            return Err(ApiError::Forbidden("You can't remove a member with the same or a higher role".to_string()));
+        }
+    }
+
+    let removed = sqlx::query!(
+        "DELETE FROM chat_members WHERE chat_id = $1 AND user_id = $2 AND role <> 'owner'",
+        chat_id,
+        user_id
+    )
+        .execute(pool.inner())
+        .await
+        .context("Failed to remove member")?;
+
+    if removed.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("User '{}' is not a member of this chat", user_id)));
+    }
+
+    hub.forget_chat(chat_id);
+
+    // Видалений учасник теж отримує подію, щоб прибрати чат зі списку
+    let event = ServerEvent::MemberRemoved { chat_id, user_id };
+    hub.send_to_users(&[user_id], &event.to_message());
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    let message = if user_id == auth.user_id {
+        "You left the chat"
+    } else {
+        "Member removed successfully!"
+    };
+
+    Ok(Json(MessageOnlyResponse {
+        message: message.to_string(),
+    }))
+}
+
+/// Only the owner promotes and demotes admins; admins can switch members between
+/// `member` and `read_only`. Ownership moves through `transfer_ownership` only.
+#[openapi(tag = "Groups")]
+#[put("/<chat_id>/members/<user_id>/role", format = "json", data = "<role_request>")]
+pub async fn update_member_role(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    user_id: i32,
+    role_request: Json<UpdateMemberRoleRequest>,
+) -> Result<Json<Vec<ChatMemberResponse>>, ApiError> {
+    let role = role_request.role;
+
+    if role == ChatRole::Owner {
+        return Err(ApiError::BadRequest("Use the ownership transfer to make someone the owner".to_string()));
+    }
+
+    let access = require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ChangeRoles).await?;
+    let current_role = target_role(pool.inner(), chat_id, user_id).await?;
+
+    let allowed = match (current_role, role) {
+        (ChatRole::Owner, _) => false,
+        (ChatRole::Admin, _) | (_, ChatRole::Admin) => access.role == ChatRole::Owner,
+        _ => role_rank(access.role) > role_rank(current_role),
+    };
+
+    if !allowed {
+        return Err(ApiError::Forbidden("You can't change the role of this member".to_string()));
+    }
+
+    let failed = "Failed to change member role";
+
+    sqlx::query!(
+        "UPDATE chat_members SET role = $1 WHERE chat_id = $2 AND user_id = $3",
+        role as ChatRole,
+        chat_id,
+        user_id
+    )
+        .execute(pool.inner())
+        .await
+        .context(failed)?;
+
+    if current_role != role {
+        let event = ServerEvent::MemberRoleChanged { chat_id, user_id, role };
+        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+    }
+
+    let members = fetch_chat_members(pool.inner(), chat_id).await.context(failed)?;
+
+    Ok(Json(members))
+}
+
+/// Makes another member the owner; the previous owner stays on as an admin.
+#[openapi(tag = "Groups")]
+#[put("/<chat_id>/owner", format = "json", data = "<transfer>")]
+pub async fn transfer_ownership(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    transfer: Json<TransferOwnershipRequest>,
+) -> Result<Json<Vec<ChatMemberResponse>>, ApiError> {
+    let access = member_access(pool.inner(), chat_id, auth.user_id)
+        .await
+        .context("Failed to transfer ownership")?
+        .ok_or_else(|| ApiError::Forbidden("You are not a member of this chat".to_string()))?;
+
+    if access.role != ChatRole::Owner {
+        return Err(ApiError::Forbidden("Only the owner can transfer the ownership".to_string()));
+    }
+
+    if transfer.user_id == auth.user_id {
+        return Err(ApiError::BadRequest("You already own this chat".to_string()));
+    }
+
+    let failed = "Failed to transfer ownership";
+    let mut tx = pool.begin().await.context(failed)?;
+
+    // Спочатку знімаємо роль власника, бо власник у чаті може бути лише один
+    sqlx::query!(
+        "UPDATE chat_members SET role = 'admin' WHERE chat_id = $1 AND user_id = $2",
+        chat_id,
+        auth.user_id
+    )
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    let promoted = sqlx::query!(
+        "UPDATE chat_members SET role = 'owner' WHERE chat_id = $1 AND user_id = $2 AND request_status = 'accepted'",
+        chat_id,
+        transfer.user_id
+    )
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    if promoted.rows_affected() == 0 {
+        let message = format!("User '{}' is not an active member of this chat", transfer.user_id);
+        return Err(ApiError::NotFound(message));
+    }
+
+    tx.commit().await.context(failed)?;
+
+    for (user_id, role) in [(auth.user_id, ChatRole::Admin), (transfer.user_id, ChatRole::Owner)] {
+        let event = ServerEvent::MemberRoleChanged { chat_id, user_id, role };
+        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+    }
+
+    let members = fetch_chat_members(pool.inner(), chat_id).await.context(failed)?;
+
+    Ok(Json(members))
+}
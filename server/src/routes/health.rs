@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sqlx::PgPool;
+use crate::config::Config;
+use crate::dtos::health::{HealthResponse, ReadinessCheck, ReadinessResponse};
+use crate::lifecycle::Lifecycle;
+use crate::services::health::{check_database, check_migrations, check_storage};
+
+fn check(name: &'static str, result: Result<(), &'static str>) -> ReadinessCheck {
+    ReadinessCheck { name, ok: result.is_ok(), error: result.err() }
+}
+
+/// Liveness: the process is up and serving HTTP.
+#[get("/healthz")]
+pub fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+/// Readiness: 503 while a dependency is unavailable or the server is shutting down,
+/// so the load balancer stops sending new traffic.
+#[get("/readyz")]
+pub async fn readyz(
+    pool: &State<PgPool>,
+    config: &State<Config>,
+    lifecycle: &State<Arc<Lifecycle>>,
+) -> (Status, Json<ReadinessResponse>) {
+    let (database, migrations, storage) = tokio::join!(
+        check_database(pool.inner()),
+        check_migrations(pool.inner()),
+        check_storage(&config.uploads.directory),
+    );
+
+    let websocket = if lifecycle.is_websocket_listening() {
+        Ok(())
+    } else {
+        Err("WebSocket listener is not accepting connections")
+    };
+
+    let shutdown = if lifecycle.is_shutting_down() {
+        Err("server is shutting down")
+    } else {
+        Ok(())
+    };
+
+    let checks = vec![
+        check("database", database),
+        check("migrations", migrations),
+        check("storage", storage),
+        check("websocket", websocket),
+        check("shutdown", shutdown),
+    ];
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready { Status::Ok } else { Status::ServiceUnavailable };
+
+    (status, Json(ReadinessResponse { ready, checks }))
+}
@@ -0,0 +1,355 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::PgPool;
+use sqlx::types::chrono::Utc;
+use crate::constants::common::MAX_GROUP_MEMBERS;
+use crate::guards::auth::AuthGuard;
+use crate::dtos::chat::ChatRole;
+use crate::dtos::invite::{CreateInviteRequest, InvitePreview, InviteResponse, InviteUseResponse, InviteUseStatus, JoinChatResponse, JoinRequestAction, ReviewJoinRequest};
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::errors::{ApiError, ErrorContext};
+use crate::routes::groups::require_permission;
+use crate::services::chats::{lock_member_count, member_count, notify_members};
+use crate::services::invites::{chat_admin_ids, fetch_invite_uses, fetch_invites, join_chat};
+use crate::services::permissions::ChatPermission;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+use std::sync::Arc;
+
+fn invalid_invite() -> ApiError {
+    ApiError::NotFound("Invite link is invalid or has expired".to_string())
+}
+
+fn group_is_full() -> ApiError {
+    let message = format!("A group can have at most {} members", MAX_GROUP_MEMBERS);
+    ApiError::Conflict(message)
+}
+
+#[openapi(tag = "Invites")]
+#[post("/<chat_id>/invites", format = "json", data = "<invite>")]
+pub async fn create_invite(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+    invite: Json<CreateInviteRequest>,
+) -> Result<Json<InviteResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ManageInvites).await?;
+
+    if invite.max_uses.is_some_and(|max_uses| max_uses < 1) {
+        return Err(ApiError::invalid_field("max_uses", "max_uses must be at least 1"));
+    }
+
+    if invite.expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc()) {
+        return Err(ApiError::invalid_field("expires_at", "expires_at must be in the future"));
+    }
+
+    let failed = "Failed to create invite link";
+
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO chat_invites (chat_id, created_by, expires_at, max_uses, requires_approval)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+        chat_id,
+        auth.user_id,
+        invite.expires_at,
+        invite.max_uses,
+        invite.requires_approval
+    )
+        .fetch_one(pool.inner())
+        .await
+        .context(failed)?;
+
+    fetch_invites(pool.inner(), chat_id, Some(created.id))
+        .await
+        .context(failed)?
+        .pop()
+        .map(Json)
+        .ok_or_else(|| ApiError::Internal(failed.to_string()))
+}
+
+#[openapi(tag = "Invites")]
+#[get("/<chat_id>/invites", format = "json")]
+pub async fn get_invites(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+) -> Result<Json<Vec<InviteResponse>>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ManageInvites).await?;
+
+    let invites = fetch_invites(pool.inner(), chat_id, None)
+        .await
+        .context("Failed to fetch invite links")?;
+
+    Ok(Json(invites))
+}
+
+/// Revoked links can't be used anymore; join requests made through them can still be reviewed.
+#[openapi(tag = "Invites")]
+#[delete("/<chat_id>/invites/<invite_id>")]
+pub async fn revoke_invite(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+    invite_id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ManageInvites).await?;
+
+    let revoked = sqlx::query!(
+        "UPDATE chat_invites SET revoked_at = NOW() WHERE id = $1 AND chat_id = $2 AND revoked_at IS NULL",
+        invite_id,
+        chat_id
+    )
+        .execute(pool.inner())
+        .await
+        .context("Failed to revoke invite link")?;
+
+    if revoked.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("Active invite link with id '{}' not found", invite_id)));
+    }
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Invite link revoked successfully!".to_string(),
+    }))
+}
+
+#[openapi(tag = "Invites")]
+#[get("/<chat_id>/invites/uses?<invite_id>&<status>", format = "json")]
+pub async fn get_invite_uses(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+    invite_id: Option<i32>,
+    status: Option<InviteUseStatus>,
+) -> Result<Json<Vec<InviteUseResponse>>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ManageInvites).await?;
+
+    let uses = fetch_invite_uses(pool.inner(), chat_id, invite_id, status)
+        .await
+        .context("Failed to fetch invite link uses")?;
+
+    Ok(Json(uses))
+}
+
+#[openapi(tag = "Invites")]
+#[put("/<chat_id>/invites/uses/<use_id>", format = "json", data = "<review>")]
+pub async fn review_join_request(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    use_id: i32,
+    review: Json<ReviewJoinRequest>,
+) -> Result<Json<Vec<InviteUseResponse>>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::ManageInvites).await?;
+
+    let status = match review.action {
+        JoinRequestAction::Approve => InviteUseStatus::Approved,
+        JoinRequestAction::Reject => InviteUseStatus::Rejected,
+    };
+
+    let failed = "Failed to review join request";
+    let mut tx = pool.begin().await.context(failed)?;
+
+    let request = sqlx::query!(
+        r#"
+        UPDATE chat_invite_uses u
+        SET status = $1, reviewed_by = $2, reviewed_at = NOW()
+        FROM chat_invites i
+        WHERE u.id = $3 AND i.id = u.invite_id AND i.chat_id = $4 AND u.status = 'pending'
+        RETURNING u.user_id
+        "#,
+        status as InviteUseStatus,
+        auth.user_id,
+        use_id,
+        chat_id
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(failed)?
+        .ok_or_else(|| ApiError::NotFound(format!("Pending join request with id '{}' not found", use_id)))?;
+
+    let mut joined = false;
+
+    if status == InviteUseStatus::Approved {
+        if lock_member_count(&mut *tx, chat_id).await.context(failed)? >= MAX_GROUP_MEMBERS as i64 {
+            return Err(group_is_full());
+        }
+
+        joined = join_chat(&mut *tx, chat_id, request.user_id).await.context(failed)?;
+    }
+
+    tx.commit().await.context(failed)?;
+
+    if joined {
+        hub.forget_chat(chat_id);
+    }
+
+    let event = ServerEvent::JoinRequestReviewed { chat_id, status };
+    hub.send_to_users(&[request.user_id], &event.to_message());
+
+    if joined {
+        let event = ServerEvent::MemberAdded { chat_id, user_id: request.user_id, role: ChatRole::Member };
+        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+    }
+
+    let uses = fetch_invite_uses(pool.inner(), chat_id, None, Some(InviteUseStatus::Pending))
+        .await
+        .context(failed)?;
+
+    Ok(Json(uses))
+}
+
+#[openapi(tag = "Invites")]
+#[get("/<token>", format = "json")]
+pub async fn preview_invite(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    token: &str,
+) -> Result<Json<InvitePreview>, ApiError> {
+    let failed = "Failed to fetch invite link";
+
+    let invite = sqlx::query!(
+        r#"
+        SELECT c.id, c.title AS "title!", c.avatar_path, i.requires_approval
+        FROM chat_invites i
+        JOIN chats c ON c.id = i.chat_id
+        WHERE i.token = $1
+          AND i.revoked_at IS NULL
+          AND (i.expires_at IS NULL OR i.expires_at > NOW())
+          AND (i.max_uses IS NULL OR i.use_count < i.max_uses)
+        "#,
+        token
+    )
+        .fetch_optional(pool.inner())
+        .await
+        .context(failed)?
+        .ok_or_else(invalid_invite)?;
+
+    let membership = sqlx::query!(
+        "SELECT 1 AS joined FROM chat_members WHERE chat_id = $1 AND user_id = $2 AND request_status = 'accepted'",
+        invite.id,
+        auth.user_id
+    )
+        .fetch_optional(pool.inner())
+        .await
+        .context(failed)?;
+
+    Ok(Json(InvitePreview {
+        chat_id: invite.id,
+        title: invite.title,
+        avatar_url: invite.avatar_path.map(|path| format!("/api/files/{}", path)),
+        member_count: member_count(pool.inner(), invite.id).await.context(failed)?,
+        requires_approval: invite.requires_approval,
+        is_member: membership.is_some(),
+    }))
+}
+
+/// Joins the chat right away, or files a join request for the admins when the link requires approval.
+/// Either way the use is recorded and counts towards the link's limit.
+#[openapi(tag = "Invites")]
+#[post("/<token>/join")]
+pub async fn join_by_invite(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    token: &str,
+) -> Result<Json<JoinChatResponse>, ApiError> {
+    let failed = "Failed to join chat";
+    let mut tx = pool.begin().await.context(failed)?;
+
+    // Блокуємо посилання, щоб паралельні запити не перевищили ліміт використань
+    let invite = sqlx::query!(
+        r#"
+        SELECT id, chat_id, requires_approval
+        FROM chat_invites
+        WHERE token = $1
+          AND revoked_at IS NULL
+          AND (expires_at IS NULL OR expires_at > NOW())
+          AND (max_uses IS NULL OR use_count < max_uses)
+        FOR UPDATE
+        "#,
+        token
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(failed)?
+        .ok_or_else(invalid_invite)?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (
+                SELECT 1 FROM chat_members
+                WHERE chat_id = $1 AND user_id = $2 AND request_status = 'accepted'
+            ) AS "member!",
+            EXISTS (
+                SELECT 1 FROM chat_invite_uses u
+                JOIN chat_invites i ON i.id = u.invite_id
+                WHERE i.chat_id = $1 AND u.user_id = $2 AND u.status = 'pending'
+            ) AS "pending!"
+        "#,
+        invite.chat_id,
+        auth.user_id
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .context(failed)?;
+
+    if existing.member {
+        return Err(ApiError::Conflict("You are already a member of this chat".to_string()));
+    }
+
+    if existing.pending {
+        return Err(ApiError::Conflict("Your request to join this chat is awaiting approval".to_string()));
+    }
+
+    let status = if invite.requires_approval {
+        InviteUseStatus::Pending
+    } else {
+        if lock_member_count(&mut *tx, invite.chat_id).await.context(failed)? >= MAX_GROUP_MEMBERS as i64 {
+            return Err(group_is_full());
+        }
+
+        join_chat(&mut *tx, invite.chat_id, auth.user_id).await.context(failed)?;
+        InviteUseStatus::Approved
+    };
+
+    sqlx::query!(
+        "INSERT INTO chat_invite_uses (invite_id, user_id, status) VALUES ($1, $2, $3)",
+        invite.id,
+        auth.user_id,
+        status as InviteUseStatus
+    )
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    sqlx::query!("UPDATE chat_invites SET use_count = use_count + 1 WHERE id = $1", invite.id)
+        .execute(&mut *tx)
+        .await
+        .context(failed)?;
+
+    tx.commit().await.context(failed)?;
+
+    if status == InviteUseStatus::Pending {
+        let event = ServerEvent::JoinRequested { chat_id: invite.chat_id, invite_id: invite.id, user_id: auth.user_id };
+
+        match chat_admin_ids(pool.inner(), invite.chat_id).await {
+            Ok(admins) => hub.send_to_users(&admins, &event.to_message()),
+            Err(e) => tracing::error!(error = %e, chat_id = invite.chat_id, "Failed to fetch chat admins"),
+        }
+    } else {
+        hub.forget_chat(invite.chat_id);
+
+        let event = ServerEvent::MemberAdded { chat_id: invite.chat_id, user_id: auth.user_id, role: ChatRole::Member };
+        notify_members(pool.inner(), hub.inner(), invite.chat_id, event).await;
+    }
+
+    Ok(Json(JoinChatResponse {
+        chat_id: invite.chat_id,
+        status,
+    }))
+}
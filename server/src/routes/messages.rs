@@ -0,0 +1,431 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::PgPool;
+use crate::config::Config;
+use crate::constants::common::{MAX_THREAD_PAGE_SIZE, MESSAGE_EDIT_WINDOW_SECONDS, THREAD_PAGE_SIZE};
+use crate::errors::{ApiError, ErrorContext};
+use crate::guards::auth::AuthGuard;
+use crate::dtos::chat::{DeleteScope, EditMessageRequest, MessageEditResponse, MessageResponse, ReactionRequest, ReactionSummary, ThreadResponse};
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::routes::groups::require_permission;
+use crate::services::chats::{is_chat_member, notify_members};
+use crate::services::messages::{fetch_messages, is_file_referenced, MessageQuery};
+use crate::services::permissions::ChatPermission;
+use crate::services::reactions::{set_reaction, ReactionError};
+use crate::telemetry::Redacted;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+use std::sync::Arc;
+
+async fn ensure_member(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<(), ApiError> {
+    if is_chat_member(pool, chat_id, user_id).await.context("Failed to check chat membership")? {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden("You are not a member of this chat".to_string()))
+    }
+}
+
+fn message_not_found(message_id: i32) -> ApiError {
+    ApiError::NotFound(format!("Message with id '{}' not found", message_id))
+}
+
+fn reaction_error(error: ReactionError, message_id: i32) -> ApiError {
+    match error {
+        ReactionError::InvalidEmoji => ApiError::invalid_field("emoji", "Reaction must be a single emoji"),
+        ReactionError::MessageNotFound => message_not_found(message_id),
+        ReactionError::Database(error) => ApiError::database(error, "Failed to update reaction"),
+    }
+}
+
+#[openapi(tag = "Messages")]
+#[patch("/<chat_id>/messages/<message_id>", format = "json", data = "<edit_request>")]
+pub async fn edit_message(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    message_id: i32,
+    edit_request: Json<EditMessageRequest>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let content = edit_request.content.trim();
+
+    if content.is_empty() {
+        return Err(ApiError::BadRequest("Message content must not be empty".to_string()));
+    }
+
+    // Автор міг уже покинути чат
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    let mut tx = pool.begin().await.context("Failed to edit message")?;
+
+    let message = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            content,
+            deleted_at,
+            COALESCE(created_at >= NOW() - $3 * INTERVAL '1 second', FALSE) AS "editable!"
+        FROM messages
+        WHERE id = $1 AND chat_id = $2
+        FOR UPDATE
+        "#,
+        message_id,
+        chat_id,
+        MESSAGE_EDIT_WINDOW_SECONDS as f64
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to edit message")?
+        .ok_or_else(|| message_not_found(message_id))?;
+
+    if message.user_id != auth.user_id {
+        return Err(ApiError::Forbidden("Only the author can edit this message".to_string()));
+    }
+
+    if message.deleted_at.is_some() {
+        return Err(ApiError::Conflict("Deleted messages can't be edited".to_string()));
+    }
+
+    if !message.editable {
+        return Err(ApiError::Forbidden("The time window for editing this message has passed".to_string()));
+    }
+
+    if message.content != content {
+        // Попередня версія зберігається в історії редагувань
+        sqlx::query!(
+            "INSERT INTO message_edits (message_id, previous_content) VALUES ($1, $2)",
+            message_id,
+            message.content
+        )
+            .execute(&mut *tx)
+            .await
+            .context("Failed to edit message")?;
+
+        let edited = sqlx::query!(
+            r#"UPDATE messages SET content = $1, edited_at = NOW() WHERE id = $2 RETURNING edited_at AS "edited_at!""#,
+            content,
+            message_id
+        )
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to edit message")?;
+
+        tx.commit().await.context("Failed to edit message")?;
+
+        let event = ServerEvent::MessageEdited {
+            chat_id,
+            message_id,
+            content: content.to_string(),
+            edited_at: edited.edited_at,
+        };
+        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+    }
+
+    fetch_messages(pool.inner(), auth.user_id, chat_id, MessageQuery { message_id: Some(message_id), ..Default::default() })
+        .await
+        .context("Failed to fetch message")?
+        .pop()
+        .map(Json)
+        .ok_or_else(|| message_not_found(message_id))
+}
+
+#[openapi(tag = "Messages")]
+#[delete("/<chat_id>/messages/<message_id>?<scope>")]
+pub async fn delete_message(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    config: &State<Config>,
+    chat_id: i32,
+    message_id: i32,
+    scope: Option<DeleteScope>,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    let message = sqlx::query!(
+        "SELECT user_id, file_path, file_uploaded, deleted_at FROM messages WHERE id = $1 AND chat_id = $2",
+        message_id,
+        chat_id
+    )
+        .fetch_optional(pool.inner())
+        .await
+        .context("Failed to delete message")?
+        .ok_or_else(|| message_not_found(message_id))?;
+
+    match scope.unwrap_or(DeleteScope::Me) {
+        DeleteScope::Me => {
+            sqlx::query!(
+                "INSERT INTO hidden_messages (message_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                message_id,
+                auth.user_id
+            )
+                .execute(pool.inner())
+                .await
+                .context("Failed to delete message")?;
+
+            // Лише інші пристрої цього ж користувача
+            let event = ServerEvent::MessageDeleted { chat_id, message_id, scope: DeleteScope::Me };
+            hub.send_to_users(&[auth.user_id], &event.to_message());
+        }
+        DeleteScope::Everyone => {
+            // Чужі повідомлення можуть видаляти лише модератори групи
+            if message.user_id != auth.user_id {
+                require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::DeleteOthersMessages).await?;
+            }
+
+            if message.deleted_at.is_none() {
+                // Видалена відповідь перестає рахуватися в гілці тим самим запитом
+                let deleted = sqlx::query!(
+                    r#"
+                    WITH deleted AS (
+                        UPDATE messages
+                        SET deleted_at = NOW()
+                        WHERE id = $1 AND deleted_at IS NULL
+                        RETURNING thread_root_id
+                    ),
+                    root AS (
+                        UPDATE messages m
+                        SET reply_count = GREATEST(m.reply_count - 1, 0)
+                        FROM deleted
+                        WHERE m.id = deleted.thread_root_id
+                        RETURNING m.id, m.reply_count, m.last_reply_at
+                    )
+                    SELECT root.id AS "thread_root_id?", root.reply_count AS "reply_count?", root.last_reply_at
+                    FROM deleted
+                    LEFT JOIN root ON TRUE
+                    "#,
+                    message_id
+                )
+                    .fetch_optional(pool.inner())
+                    .await
+                    .context("Failed to delete message")?;
+
+                // Якщо повідомлення тим часом уже видалили, сповіщати нема про що
+                if let Some(deleted) = deleted {
+                    // Файл, на який ще щось посилається, лишається на диску до прибирання
+                    if let Some(file_path) = message.file_path.filter(|_| message.file_uploaded) {
+                        match is_file_referenced(pool.inner(), &file_path, message_id).await {
+                            Ok(false) => {
+                                if let Err(e) = tokio::fs::remove_file(config.uploads.path_of(&file_path)).await {
+                                    tracing::warn!(error = %e, file_path = %Redacted(&file_path), "Failed to remove file");
+                                }
+                            }
+                            Ok(true) => {}
+                            Err(e) => tracing::warn!(error = %e, file_path = %Redacted(&file_path), "Failed to check file references"),
+                        }
+                    }
+
+                    let event = ServerEvent::MessageDeleted { chat_id, message_id, scope: DeleteScope::Everyone };
+                    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+                    if let (Some(thread_root_id), Some(reply_count)) = (deleted.thread_root_id, deleted.reply_count) {
+                        let event = ServerEvent::ThreadUpdated {
+                            chat_id,
+                            message_id: thread_root_id,
+                            reply_count,
+                            last_reply_at: deleted.last_reply_at,
+                        };
+                        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Message deleted successfully!".to_string(),
+    }))
+}
+
+#[openapi(tag = "Messages")]
+#[get("/<chat_id>/messages/<message_id>/history", format = "json")]
+pub async fn get_message_history(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+    message_id: i32,
+) -> Result<Json<Vec<MessageEditResponse>>, ApiError> {
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    let message = sqlx::query!(
+        "SELECT deleted_at FROM messages WHERE id = $1 AND chat_id = $2",
+        message_id,
+        chat_id
+    )
+        .fetch_optional(pool.inner())
+        .await
+        .context("Failed to fetch message history")?
+        .ok_or_else(|| message_not_found(message_id))?;
+
+    // Видалене для всіх повідомлення не розкриває і своїх попередніх версій
+    if message.deleted_at.is_some() {
+        return Ok(Json(Vec::new()));
+    }
+
+    let edits = sqlx::query!(
+        "SELECT previous_content, edited_at FROM message_edits WHERE message_id = $1 ORDER BY edited_at ASC",
+        message_id
+    )
+        .fetch_all(pool.inner())
+        .await
+        .context("Failed to fetch message history")?;
+
+    Ok(Json(edits.into_iter().map(|edit| MessageEditResponse {
+        previous_content: edit.previous_content,
+        edited_at: edit.edited_at,
+    }).collect()))
+}
+
+#[openapi(tag = "Messages")]
+#[get("/<chat_id>/messages/<message_id>/thread?<after>&<limit>", format = "json")]
+pub async fn get_thread(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    chat_id: i32,
+    message_id: i32,
+    after: Option<i32>,
+    limit: Option<i64>,
+) -> Result<Json<ThreadResponse>, ApiError> {
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    let root = fetch_messages(pool.inner(), auth.user_id, chat_id, MessageQuery { message_id: Some(message_id), ..Default::default() })
+        .await
+        .context("Failed to fetch thread")?
+        .pop()
+        .ok_or_else(|| message_not_found(message_id))?;
+
+    if root.thread_root_id.is_some() {
+        return Err(ApiError::BadRequest("Message is a thread reply, not a thread root".to_string()));
+    }
+
+    let limit = limit.unwrap_or(THREAD_PAGE_SIZE).clamp(1, MAX_THREAD_PAGE_SIZE);
+
+    // Беремо на один більше, щоб знати, чи є наступна сторінка
+    let mut replies = fetch_messages(pool.inner(), auth.user_id, chat_id, MessageQuery {
+        thread_root_id: Some(message_id),
+        after_id: after,
+        limit: Some(limit + 1),
+        ..Default::default()
+    })
+        .await
+        .context("Failed to fetch thread")?;
+
+    let next_cursor = if replies.len() as i64 > limit {
+        replies.truncate(limit as usize);
+        replies.last().map(|reply| reply.id)
+    } else {
+        None
+    };
+
+    Ok(Json(ThreadResponse { root, replies, next_cursor }))
+}
+
+#[openapi(tag = "Messages")]
+#[put("/<chat_id>/messages/<message_id>/reactions", format = "json", data = "<reaction>")]
+pub async fn add_reaction(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    message_id: i32,
+    reaction: Json<ReactionRequest>,
+) -> Result<Json<Vec<ReactionSummary>>, ApiError> {
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    set_reaction(pool.inner(), hub.inner(), chat_id, message_id, auth.user_id, &reaction.emoji, true)
+        .await
+        .map(Json)
+        .map_err(|e| reaction_error(e, message_id))
+}
+
+#[openapi(tag = "Messages")]
+#[delete("/<chat_id>/messages/<message_id>/reactions/<emoji>")]
+pub async fn remove_reaction(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    message_id: i32,
+    emoji: &str,
+) -> Result<Json<Vec<ReactionSummary>>, ApiError> {
+    ensure_member(pool.inner(), chat_id, auth.user_id).await?;
+
+    set_reaction(pool.inner(), hub.inner(), chat_id, message_id, auth.user_id, emoji, false)
+        .await
+        .map(Json)
+        .map_err(|e| reaction_error(e, message_id))
+}
+
+#[openapi(tag = "Messages")]
+#[put("/<chat_id>/messages/<message_id>/pin")]
+pub async fn pin_message(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    message_id: i32,
+) -> Result<Json<MessageResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::PinMessages).await?;
+
+    let pinned = sqlx::query!(
+        r#"
+        INSERT INTO pinned_messages (message_id, chat_id, pinned_by)
+        SELECT id, chat_id, $3 FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL
+        ON CONFLICT (message_id) DO NOTHING
+        RETURNING message_id
+        "#,
+        message_id,
+        chat_id,
+        auth.user_id
+    )
+        .fetch_optional(pool.inner())
+        .await
+        .context("Failed to pin message")?;
+
+    if pinned.is_some() {
+        let event = ServerEvent::MessagePinned { chat_id, message_id, pinned_by: auth.user_id };
+        notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+    }
+
+    // Повторне закріплення нічого не змінює, неіснуюче повідомлення дає 404
+    fetch_messages(pool.inner(), auth.user_id, chat_id, MessageQuery { message_id: Some(message_id), ..Default::default() })
+        .await
+        .context("Failed to fetch message")?
+        .pop()
+        .filter(|message| message.pinned_at.is_some())
+        .map(Json)
+        .ok_or_else(|| message_not_found(message_id))
+}
+
+#[openapi(tag = "Messages")]
+#[delete("/<chat_id>/messages/<message_id>/pin")]
+pub async fn unpin_message(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    chat_id: i32,
+    message_id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    require_permission(pool.inner(), chat_id, auth.user_id, ChatPermission::PinMessages).await?;
+
+    let unpinned = sqlx::query!(
+        "DELETE FROM pinned_messages WHERE message_id = $1 AND chat_id = $2",
+        message_id,
+        chat_id
+    )
+        .execute(pool.inner())
+        .await
+        .context("Failed to unpin message")?;
+
+    if unpinned.rows_affected() == 0 {
+        return Err(message_not_found(message_id));
+    }
+
+    let event = ServerEvent::MessageUnpinned { chat_id, message_id };
+    notify_members(pool.inner(), hub.inner(), chat_id, event).await;
+
+    Ok(Json(MessageOnlyResponse {
+        message: "Message unpinned successfully!".to_string(),
+    }))
+}
@@ -0,0 +1,14 @@
+use rocket::State;
+use rocket::http::ContentType;
+use sqlx::PgPool;
+use crate::config::Config;
+use crate::guards::metrics::MetricsAccess;
+use crate::metrics::METRICS;
+
+#[get("/metrics")]
+pub fn metrics(_access: MetricsAccess, pool: &State<PgPool>, config: &State<Config>) -> (ContentType, String) {
+    METRICS.update_pool(pool.inner(), config.database.max_connections);
+
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, METRICS.encode())
+}
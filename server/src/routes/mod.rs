@@ -1,3 +1,12 @@
 pub mod auth;
 pub mod users;
 pub mod chats;
+pub mod messages;
+pub mod search;
+pub mod contacts;
+pub mod folders;
+pub mod groups;
+pub mod invites;
+pub mod docs;
+pub mod metrics;
+pub mod health;
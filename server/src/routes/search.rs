@@ -0,0 +1,66 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use sqlx::PgPool;
+use crate::constants::common::{MAX_SEARCH_PAGE_SIZE, SEARCH_PAGE_SIZE};
+use crate::errors::{ApiError, ErrorContext};
+use crate::guards::auth::AuthGuard;
+use crate::dtos::search::SearchResponse;
+use crate::services::search::{search_messages, SearchCursor, SearchFilters};
+use crate::utils::time::parse_date_param;
+
+fn parse_date(value: Option<&str>, name: &str) -> Result<Option<sqlx::types::chrono::NaiveDateTime>, ApiError> {
+    match value {
+        Some(value) => parse_date_param(value)
+            .map(Some)
+            .ok_or_else(|| ApiError::invalid_field(name, format!("Invalid '{}' date, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS", name))),
+        None => Ok(None),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[openapi(tag = "Search")]
+#[get("/messages?<q>&<chat_id>&<sender_id>&<from>&<to>&<message_type>&<cursor>&<limit>", format = "json")]
+pub async fn search(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    q: &str,
+    chat_id: Option<i32>,
+    sender_id: Option<i32>,
+    from: Option<&str>,
+    to: Option<&str>,
+    message_type: Option<&str>,
+    cursor: Option<&str>,
+    limit: Option<i64>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let query = q.trim();
+
+    if query.is_empty() {
+        return Err(ApiError::invalid_field("q", "Search query must not be empty"));
+    }
+
+    let cursor = match cursor {
+        Some(cursor) => Some(SearchCursor::decode(cursor).ok_or_else(|| ApiError::invalid_field("cursor", "Invalid cursor"))?),
+        None => None,
+    };
+
+    let filters = SearchFilters {
+        query: query.to_string(),
+        chat_id,
+        sender_id,
+        from: parse_date(from, "from")?,
+        to: parse_date(to, "to")?,
+        message_type: message_type.map(|message_type| message_type.to_string()),
+        cursor,
+        limit: limit.unwrap_or(SEARCH_PAGE_SIZE).clamp(1, MAX_SEARCH_PAGE_SIZE),
+    };
+
+    let (results, next_cursor) = search_messages(pool.inner(), auth.user_id, &filters)
+        .await
+        .context("Failed to search messages")?;
+
+    Ok(Json(SearchResponse {
+        results,
+        next_cursor: next_cursor.map(|cursor| cursor.encode()),
+    }))
+}
@@ -1,62 +1,388 @@
 use rocket::State;
-use rocket::serde::json::{Json, Value};
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
 use sqlx::PgPool;
 use crate::guards::auth::AuthGuard;
-use crate::dtos::user::UserWithoutPassword;
-use rocket::http::Status;
-use rocket::response::status;
+use rocket::data::Data;
+use crate::config::Config;
+use crate::constants::common::{MAX_BIO_LENGTH, MAX_DISPLAY_NAME_LENGTH, MAX_STATUS_TEXT_LENGTH, MAX_USER_SEARCH_PAGE_SIZE, MAX_USER_SEARCH_RESULTS, MIN_USER_SEARCH_LENGTH, USER_SEARCH_PAGE_SIZE};
+use crate::dtos::responses::MessageOnlyResponse;
+use crate::errors::{ApiError, ErrorContext};
+use crate::dtos::user::{BlockedUser, DirectChatPolicy, PresenceResponse, PrivacySettings, PresenceStatus, UpdatePrivacySettingsRequest, UpdateProfileRequest, UserProfileResponse, UserSearchResponse};
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+use crate::websockets::server::announce_presence_change;
+use crate::services::blocks::{block_user, fetch_blocked_users, unblock_user};
+use crate::services::chats::chat_partner_ids;
+use crate::services::users::{fetch_profile, is_discoverable_by, search_users};
+use crate::utils::time::get_current_timestamp;
+use crate::utils::images::{save_avatar, AvatarError};
+use std::sync::Arc;
 
 
-#[get("/", format = "json")]
+#[openapi(tag = "Users")]
+#[get("/?<q>&<offset>&<limit>", format = "json")]
 pub async fn get_users(
-    _auth: AuthGuard,
+    auth: AuthGuard,
     pool: &State<PgPool>,
-) -> Result<Json<Vec<UserWithoutPassword>>, status::Custom<Json<Value>>> {
+    q: Option<&str>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Json<UserSearchResponse>, ApiError> {
+    let query = q.unwrap_or_default().trim();
+
+    // Без мінімальної довжини запиту пошук перетворюється на вивантаження всіх користувачів
+    if query.chars().count() < MIN_USER_SEARCH_LENGTH {
+        return Err(ApiError::invalid_field("q", format!("Search query must be at least {} characters long", MIN_USER_SEARCH_LENGTH)));
+    }
+
+    let offset = offset.unwrap_or(0).max(0);
+    let limit = limit.unwrap_or(USER_SEARCH_PAGE_SIZE).clamp(1, MAX_USER_SEARCH_PAGE_SIZE);
+    let limit = limit.min(MAX_USER_SEARCH_RESULTS - offset);
+
+    if limit <= 0 {
+        return Ok(Json(UserSearchResponse { users: Vec::new(), next_offset: None }));
+    }
+
+    let mut users = search_users(pool.inner(), auth.user_id, query, offset, limit + 1)
+        .await
+        .context("Failed to fetch users")?;
+
+    let next_offset = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        Some(offset + limit).filter(|next| *next < MAX_USER_SEARCH_RESULTS)
+    } else {
+        None
+    };
+
+    Ok(Json(UserSearchResponse { users, next_offset }))
+}
+
+#[openapi(tag = "Users")]
+#[get("/<id>", format = "json")]
+pub async fn get_user(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    id: i32,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    let not_found = || ApiError::NotFound("User not found".to_string());
+
+    // Користувачі, яких не можна знайти в довіднику, виглядають так само, як неіснуючі
+    let discoverable = is_discoverable_by(pool.inner(), auth.user_id, id).await.unwrap_or(false);
+
+    if !discoverable {
+        return Err(not_found());
+    }
+
+    let profile = fetch_profile(pool.inner(), id, id == auth.user_id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(not_found)?;
+
+    Ok(Json(profile))
+}
+
+#[openapi(tag = "Users")]
+#[get("/me/profile", format = "json")]
+pub async fn get_profile(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    let profile = fetch_profile(pool.inner(), auth.user_id, true)
+        .await
+        .context("Failed to fetch profile")?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    Ok(Json(profile))
+}
+
+/// Trims the field and checks its length. Empty strings are kept: they clear the field.
+fn validate_profile_field(field: &str, name: &str, value: &Option<String>, max_length: usize) -> Result<Option<String>, ApiError> {
+    let value = value.as_deref().map(str::trim);
+
+    if value.is_some_and(|value| value.chars().count() > max_length) {
+        return Err(ApiError::invalid_field(field, format!("{} must be at most {} characters long", name, max_length)));
+    }
+
+    Ok(value.map(str::to_string))
+}
+
+#[openapi(tag = "Users")]
+#[patch("/me/profile", format = "json", data = "<profile>")]
+pub async fn update_profile(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    profile: Json<UpdateProfileRequest>,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    let display_name = validate_profile_field("display_name", "Display name", &profile.display_name, MAX_DISPLAY_NAME_LENGTH)?;
+    let bio = validate_profile_field("bio", "Bio", &profile.bio, MAX_BIO_LENGTH)?;
+    let status_text = validate_profile_field("status_text", "Status", &profile.status_text, MAX_STATUS_TEXT_LENGTH)?;
+
+    // Новий статус без терміну дії діє безстроково, очищений статус втрачає і термін
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET display_name = CASE WHEN $1::TEXT IS NULL THEN display_name ELSE NULLIF($1, '') END,
+            bio = CASE WHEN $2::TEXT IS NULL THEN bio ELSE NULLIF($2, '') END,
+            status_text = CASE WHEN $3::TEXT IS NULL THEN status_text ELSE NULLIF($3, '') END,
+            status_expires_at = CASE
+                WHEN $3::TEXT IS NULL THEN COALESCE($4, status_expires_at)
+                WHEN $3 = '' THEN NULL
+                ELSE $4
+            END
+        WHERE id = $5
+        "#,
+        display_name,
+        bio,
+        status_text,
+        profile.status_expires_at,
+        auth.user_id
+    )
+        .execute(pool.inner())
+        .await
+        .context("Failed to update profile")?;
+
+    get_profile(auth, pool).await
+}
+
+#[openapi(tag = "Users")]
+#[put("/me/avatar", data = "<data>")]
+pub async fn upload_avatar(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    config: &State<Config>,
+    data: Data<'_>,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    let file_name = format!("avatar_{}_{}.png", auth.user_id, get_current_timestamp());
+
+    match save_avatar(data, &config.uploads, &file_name).await {
+        Ok(()) => {}
+        Err(AvatarError::TooLarge) => return Err(ApiError::BadRequest("Avatar is too large".to_string())),
+        Err(AvatarError::UnsupportedFormat) => return Err(ApiError::BadRequest("Unsupported image format".to_string())),
+        Err(AvatarError::Io(_)) => return Err(ApiError::Internal("Failed to save avatar".to_string())),
+    }
+
+    let previous = sqlx::query!(
+        r#"
+        UPDATE users u
+        SET avatar_path = $1
+        FROM users old
+        WHERE u.id = $2 AND old.id = u.id
+        RETURNING old.avatar_path
+        "#,
+        file_name,
+        auth.user_id
+    )
+        .fetch_one(pool.inner())
+        .await
+        .context("Failed to save avatar")?;
+
+    // Попередній аватар більше ніде не використовується
+    if let Some(previous) = previous.avatar_path.filter(|previous| *previous != file_name) {
+        let _ = tokio::fs::remove_file(config.uploads.path_of(&previous)).await;
+    }
+
+    get_profile(auth, pool).await
+}
+
+#[openapi(tag = "Users")]
+#[get("/presence", format = "json")]
+pub async fn get_presence(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+) -> Result<Json<Vec<PresenceResponse>>, ApiError> {
+    // Присутність видно лише тим, з ким є спільний чат і кого не заблоковано
     let users = sqlx::query!(
-        "SELECT id, email FROM users"
+        r#"
+        SELECT
+            id,
+            hide_presence OR EXISTS (
+                SELECT 1 FROM user_blocks b WHERE b.blocker_id = users.id AND b.blocked_id = $1
+            ) AS "hide_presence!",
+            last_seen_at
+        FROM users
+        WHERE id IN (
+            SELECT other.user_id
+            FROM chat_members me
+            JOIN chat_members other ON other.chat_id = me.chat_id AND other.user_id <> me.user_id
+            WHERE me.user_id = $1
+        )
+        "#,
+        auth.user_id
     )
         .fetch_all(pool.inner())
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": "Failed to fetch users"
-        });
-            status::Custom(Status::InternalServerError, Json(error_message)) // Повертаємо JSON з помилкою
-        })?;
-
-    let users_response: Vec<UserWithoutPassword> = users.into_iter().map(|user| {
-        UserWithoutPassword {
-            id: Some(user.id),
-            email: user.email,
+        .context("Failed to fetch presence")?;
+
+    let presence_response: Vec<PresenceResponse> = users.into_iter().map(|user| {
+        if user.hide_presence {
+            PresenceResponse { user_id: user.id, status: None, last_seen: None }
+        } else {
+            PresenceResponse {
+                user_id: user.id,
+                status: Some(hub.presence(user.id)),
+                last_seen: user.last_seen_at,
+            }
         }
     }).collect();
 
-    Ok(Json(users_response))
+    Ok(Json(presence_response))
 }
 
-#[get("/<id>", format = "json")]
-pub async fn get_user(
-    _auth: AuthGuard,
+#[openapi(tag = "Users")]
+#[get("/me/privacy", format = "json")]
+pub async fn get_privacy_settings(
+    auth: AuthGuard,
     pool: &State<PgPool>,
-    id: i32,
-) -> Result<Json<UserWithoutPassword>, status::Custom<Json<Value>>> {
-    let user = sqlx::query!(
-        "SELECT id, email FROM users WHERE id = $1",
-        id
+) -> Result<Json<PrivacySettings>, ApiError> {
+    let settings = sqlx::query!(
+        r#"SELECT hide_presence, discoverable, direct_chat_policy AS "direct_chat_policy: DirectChatPolicy" FROM users WHERE id = $1"#,
+        auth.user_id
     )
         .fetch_one(pool.inner())
         .await
-        .map_err(|_| {
-            let error_message = serde_json::json!({
-            "message": "User not found"
-        });
-            status::Custom(Status::NotFound, Json(error_message))
-        })?;
+        .context("Failed to fetch privacy settings")?;
 
-    let user_response = UserWithoutPassword {
-        id: Some(user.id),
-        email: user.email,
-    };
+    Ok(Json(PrivacySettings {
+        hide_presence: settings.hide_presence,
+        discoverable: settings.discoverable,
+        direct_chat_policy: settings.direct_chat_policy,
+    }))
+}
+
+#[openapi(tag = "Users")]
+#[put("/me/privacy", format = "json", data = "<settings>")]
+pub async fn update_privacy_settings(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    settings: Json<UpdatePrivacySettingsRequest>,
+) -> Result<Json<PrivacySettings>, ApiError> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET hide_presence = COALESCE($1, hide_presence),
+            discoverable = COALESCE($2, discoverable),
+            direct_chat_policy = COALESCE($3, direct_chat_policy)
+        WHERE id = $4
+        RETURNING hide_presence, discoverable, direct_chat_policy AS "direct_chat_policy: DirectChatPolicy"
+        "#,
+        settings.hide_presence,
+        settings.discoverable,
+        settings.direct_chat_policy as Option<DirectChatPolicy>,
+        auth.user_id
+    )
+        .fetch_one(pool.inner())
+        .await
+        .context("Failed to update privacy settings")?;
+
+    match settings.hide_presence {
+        Some(true) => {
+            // Для співрозмовників користувач тепер виглядає офлайн
+            if let Ok(partners) = chat_partner_ids(pool.inner(), auth.user_id).await {
+                let event = ServerEvent::PresenceChanged {
+                    user_id: auth.user_id,
+                    status: PresenceStatus::Offline,
+                    last_seen: None,
+                };
+                hub.send_to_users(&partners, &event.to_message());
+            }
+        }
+        Some(false) => {
+            hub.forget_presence(auth.user_id);
+            announce_presence_change(hub.inner(), pool.inner(), auth.user_id).await;
+        }
+        None => {}
+    }
+
+    Ok(Json(PrivacySettings {
+        hide_presence: updated.hide_presence,
+        discoverable: updated.discoverable,
+        direct_chat_policy: updated.direct_chat_policy,
+    }))
+}
+
+#[openapi(tag = "Users")]
+#[get("/me/blocked", format = "json")]
+pub async fn get_blocked_users(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<BlockedUser>>, ApiError> {
+    let blocked = fetch_blocked_users(pool.inner(), auth.user_id)
+        .await
+        .context("Failed to fetch blocked users")?;
 
-    Ok(Json(user_response))
-}
\ No newline at end of file
+    Ok(Json(blocked))
+}
+
+#[openapi(tag = "Users")]
+#[put("/<id>/block")]
+pub async fn block(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    if id == auth.user_id {
+        return Err(ApiError::BadRequest("You can't block yourself".to_string()));
+    }
+
+    let exists = sqlx::query!("SELECT id FROM users WHERE id = $1", id)
+        .fetch_optional(pool.inner())
+        .await
+        .context("Failed to block user")?;
+
+    if exists.is_none() {
+        return Err(ApiError::NotFound("User not found".to_string()));
+    }
+
+    let mut conn = pool.acquire().await.context("Failed to block user")?;
+    let blocked = block_user(&mut *conn, auth.user_id, id)
+        .await
+        .context("Failed to block user")?;
+
+    if blocked {
+        hub.forget_member_chats(auth.user_id);
+
+        // Для заблокованого користувач тепер виглядає офлайн
+        let event = ServerEvent::PresenceChanged {
+            user_id: auth.user_id,
+            status: PresenceStatus::Offline,
+            last_seen: None,
+        };
+        hub.send_to_users(&[id], &event.to_message());
+    }
+
+    Ok(Json(MessageOnlyResponse {
+        message: "User blocked successfully!".to_string(),
+    }))
+}
+
+#[openapi(tag = "Users")]
+#[delete("/<id>/block")]
+pub async fn unblock(
+    auth: AuthGuard,
+    pool: &State<PgPool>,
+    hub: &State<Arc<Hub>>,
+    id: i32,
+) -> Result<Json<MessageOnlyResponse>, ApiError> {
+    let unblocked = unblock_user(pool.inner(), auth.user_id, id)
+        .await
+        .context("Failed to unblock user")?;
+
+    if !unblocked {
+        return Err(ApiError::NotFound("User is not blocked".to_string()));
+    }
+
+    hub.forget_member_chats(auth.user_id);
+
+    // Повідомляємо актуальну присутність тим, хто знову може її бачити
+    hub.forget_presence(auth.user_id);
+    announce_presence_change(hub.inner(), pool.inner(), auth.user_id).await;
+
+    Ok(Json(MessageOnlyResponse {
+        message: "User unblocked successfully!".to_string(),
+    }))
+}
@@ -0,0 +1,113 @@
+use sqlx::{PgConnection, PgPool};
+use crate::dtos::user::{BlockedUser, UserSummary};
+
+/// Blocks the user. Returns `false` if they were already blocked.
+pub async fn block_user(conn: &mut PgConnection, blocker_id: i32, blocked_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO user_blocks (blocker_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        blocker_id,
+        blocked_id
+    )
+        .execute(conn)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Unblocks the user. Returns `false` if they were not blocked.
+pub async fn unblock_user(pool: &PgPool, blocker_id: i32, blocked_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM user_blocks WHERE blocker_id = $1 AND blocked_id = $2",
+        blocker_id,
+        blocked_id
+    )
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Users blocked by the given user, most recently blocked first.
+pub async fn fetch_blocked_users(pool: &PgPool, blocker_id: i32) -> Result<Vec<BlockedUser>, sqlx::Error> {
+    let blocked = sqlx::query!(
+        r#"
+        SELECT
+            s.id AS "id!",
+            s.display_name AS "display_name!",
+            s.avatar_path,
+            s.status_text,
+            b.created_at
+        FROM user_blocks b
+        JOIN user_summaries s ON s.id = b.blocked_id
+        WHERE b.blocker_id = $1
+        ORDER BY b.created_at DESC
+        "#,
+        blocker_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(blocked.into_iter().map(|blocked| BlockedUser {
+        user: UserSummary::new(blocked.id, blocked.display_name, blocked.avatar_path, blocked.status_text),
+        blocked_at: blocked.created_at,
+    }).collect())
+}
+
+/// Those of `user_ids` the given user has blocked.
+pub async fn blocked_ids(pool: &PgPool, user_id: i32, user_ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    let blocked = sqlx::query!(
+        "SELECT blocked_id FROM user_blocks WHERE blocker_id = $1 AND blocked_id = ANY($2)",
+        user_id,
+        user_ids
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(blocked.into_iter().map(|block| block.blocked_id).collect())
+}
+
+/// Whether the initiator may open a direct chat with the target: neither of them blocked
+/// the other and the target's direct chat policy lets the initiator in.
+pub async fn can_start_direct_chat(pool: &PgPool, initiator_id: i32, target_id: i32) -> Result<bool, sqlx::Error> {
+    let target = sqlx::query!(
+        r#"
+        SELECT
+            CASE u.direct_chat_policy
+                WHEN 'everyone' THEN TRUE
+                WHEN 'nobody' THEN FALSE
+                ELSE EXISTS (SELECT 1 FROM contacts c WHERE c.user_id = u.id AND c.contact_id = $1)
+            END AS "allowed!"
+        FROM users u
+        WHERE u.id = $2
+          AND NOT EXISTS (
+              SELECT 1
+              FROM user_blocks b
+              WHERE (b.blocker_id = $1 AND b.blocked_id = $2)
+                 OR (b.blocker_id = $2 AND b.blocked_id = $1)
+          )
+        "#,
+        initiator_id,
+        target_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(target.is_some_and(|target| target.allowed))
+}
+
+/// Whether either of the two users blocked the other.
+pub async fn is_blocked_between(pool: &PgPool, user_id: i32, other_id: i32) -> Result<bool, sqlx::Error> {
+    let block = sqlx::query!(
+        r#"
+        SELECT blocker_id
+        FROM user_blocks
+        WHERE (blocker_id = $1 AND blocked_id = $2) OR (blocker_id = $2 AND blocked_id = $1)
+        "#,
+        user_id,
+        other_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(block.is_some())
+}
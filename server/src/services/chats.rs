@@ -0,0 +1,455 @@
+use sqlx::{PgConnection, PgPool};
+use crate::constants::common::MESSAGE_SNIPPET_LENGTH;
+use crate::dtos::chat::{ChatMemberResponse, ChatRequestStatus, ChatResponse, ChatRole, ChatSettings, LastMessagePreview};
+use crate::dtos::user::UserSummary;
+use crate::utils::text::snippet;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::{ChatAudience, Hub};
+
+/// Ids of every user taking part in the chat. Empty if the chat does not exist.
+pub async fn chat_member_ids(pool: &PgPool, chat_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    let members = sqlx::query!(
+        "SELECT user_id FROM chat_members WHERE chat_id = $1",
+        chat_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(members.into_iter().map(|member| member.user_id).collect())
+}
+
+pub async fn is_chat_member(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<bool, sqlx::Error> {
+    let member = sqlx::query!(
+        "SELECT user_id FROM chat_members WHERE chat_id = $1 AND user_id = $2",
+        chat_id,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(member.is_some())
+}
+
+/// Sends the event to every member of the chat.
+pub async fn notify_members(pool: &PgPool, hub: &Hub, chat_id: i32, event: ServerEvent) {
+    match chat_member_ids(pool, chat_id).await {
+        Ok(members) => hub.send_to_users(&members, &event.to_message()),
+        Err(e) => tracing::error!(error = %e, chat_id, "Failed to fetch chat members"),
+    }
+}
+
+/// Number of members who have not declined the chat.
+pub async fn member_count(pool: &PgPool, chat_id: i32) -> Result<i64, sqlx::Error> {
+    let members = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM chat_members WHERE chat_id = $1 AND request_status <> 'declined'"#,
+        chat_id
+    )
+        .fetch_one(pool)
+        .await?;
+
+    Ok(members.count)
+}
+
+/// Locks the chat row until the transaction ends and counts its members who have not declined,
+/// so that concurrent joins see each other and can't go over the member limit together.
+pub async fn lock_member_count(conn: &mut PgConnection, chat_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query!("SELECT id FROM chats WHERE id = $1 FOR UPDATE", chat_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    let members = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM chat_members WHERE chat_id = $1 AND request_status <> 'declined'"#,
+        chat_id
+    )
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok(members.count)
+}
+
+/// Whether a message may go into a chat whose request is still pending.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RequestMessage {
+    /// No request is pending, or the chat is a group; nothing was taken.
+    NotNeeded,
+    /// The requester's one message was taken for this send.
+    Taken,
+    /// The requester has already sent their one message.
+    AlreadySent,
+}
+
+/// Checks whether the user may post to the chat now. In a direct chat whose other member has yet
+/// to accept the request, the requester may send only one message; it is taken here, so
+/// concurrent sends can't both get it. Pending group members don't limit anyone.
+pub async fn take_request_message(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<RequestMessage, sqlx::Error> {
+    let request = sqlx::query!(
+        r#"
+        WITH request AS (
+            SELECT EXISTS (
+                SELECT 1
+                FROM chat_members cm
+                JOIN chats c ON c.id = cm.chat_id
+                WHERE cm.chat_id = $1 AND cm.user_id <> $2 AND cm.request_status = 'pending' AND NOT c.is_group
+            ) AS pending
+        ),
+        taken AS (
+            UPDATE chat_members
+            SET request_message_sent = TRUE
+            WHERE chat_id = $1 AND user_id = $2 AND NOT request_message_sent
+              AND (SELECT pending FROM request)
+            RETURNING user_id
+        )
+        SELECT pending AS "pending!", EXISTS (SELECT 1 FROM taken) AS "taken!"
+        FROM request
+        "#,
+        chat_id,
+        user_id
+    )
+        .fetch_one(pool)
+        .await?;
+
+    Ok(match (request.pending, request.taken) {
+        (false, _) => RequestMessage::NotNeeded,
+        (true, true) => RequestMessage::Taken,
+        (true, false) => RequestMessage::AlreadySent,
+    })
+}
+
+/// Gives back the message `take_request_message` took for a send that could not be stored.
+pub async fn return_request_message(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE chat_members SET request_message_sent = FALSE WHERE chat_id = $1 AND user_id = $2 AND request_message_sent",
+        chat_id,
+        user_id
+    )
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Members of the chat with their roles, the owner and admins first.
+/// Members who declined the chat are left out.
+pub async fn fetch_chat_members(pool: &PgPool, chat_id: i32) -> Result<Vec<ChatMemberResponse>, sqlx::Error> {
+    let members = sqlx::query!(
+        r#"
+        SELECT
+            s.id AS "id!",
+            s.display_name AS "display_name!",
+            s.avatar_path,
+            s.status_text,
+            cm.role AS "role: ChatRole",
+            cm.request_status AS "request_status: ChatRequestStatus",
+            cm.joined_at
+        FROM chat_members cm
+        JOIN user_summaries s ON s.id = cm.user_id
+        WHERE cm.chat_id = $1 AND cm.request_status <> 'declined'
+        ORDER BY cm.role ASC, cm.joined_at ASC, s.id ASC
+        "#,
+        chat_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(members.into_iter().map(|member| ChatMemberResponse {
+        user: UserSummary::new(member.id, member.display_name, member.avatar_path, member.status_text),
+        role: member.role,
+        request_status: member.request_status,
+        joined_at: member.joined_at,
+    }).collect())
+}
+
+/// Members who receive what the sender posts in the chat: everyone but those who
+/// declined the chat or blocked the sender. Read from the hub, which loads the chat's
+/// audience from the database only the first time.
+pub async fn chat_recipient_ids(pool: &PgPool, hub: &Hub, chat_id: i32, sender_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    if let Some(recipients) = hub.chat_recipients(chat_id, sender_id) {
+        return Ok(recipients);
+    }
+
+    let version = hub.chats_version();
+    let audience = fetch_chat_audience(pool, chat_id).await?;
+    let recipients = audience.members
+        .iter()
+        .copied()
+        .filter(|member| !audience.blocks.contains(&(*member, sender_id)))
+        .collect();
+
+    hub.remember_chat(chat_id, audience, version);
+
+    Ok(recipients)
+}
+
+async fn fetch_chat_audience(pool: &PgPool, chat_id: i32) -> Result<ChatAudience, sqlx::Error> {
+    let members = sqlx::query_scalar!(
+        "SELECT user_id FROM chat_members WHERE chat_id = $1 AND request_status <> 'declined'",
+        chat_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let blocks = sqlx::query!(
+        r#"
+        SELECT b.blocker_id, b.blocked_id
+        FROM user_blocks b
+        JOIN chat_members blocker ON blocker.chat_id = $1 AND blocker.user_id = b.blocker_id
+        JOIN chat_members blocked ON blocked.chat_id = $1 AND blocked.user_id = b.blocked_id
+        "#,
+        chat_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(ChatAudience {
+        members,
+        blocks: blocks.into_iter().map(|block| (block.blocker_id, block.blocked_id)).collect(),
+    })
+}
+
+/// Ids of every user the given user shares at least one chat with.
+pub async fn chat_partner_ids(pool: &PgPool, user_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    let partners = sqlx::query!(
+        r#"
+        SELECT DISTINCT other.user_id
+        FROM chat_members me
+        JOIN chat_members other ON other.chat_id = me.chat_id AND other.user_id <> me.user_id
+        WHERE me.user_id = $1
+        "#,
+        user_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(partners.into_iter().map(|partner| partner.user_id).collect())
+}
+
+/// Narrows down which chats `fetch_chats` returns.
+/// By default that is the chat list, i.e. every accepted chat that is not archived.
+#[derive(Default)]
+pub struct ChatQuery {
+    pub chat_id: Option<i32>,
+    pub requests: bool,
+    pub archived: bool,
+    pub folder_id: Option<i32>,
+}
+
+/// Chats of the user as shown in their chat list: pinned chats in their order,
+/// then the rest, most recently active first.
+/// With `chat_id` set only that chat is returned, provided the user is a member
+/// and did not decline it; with `requests` set the pending chat requests are returned
+/// and with `archived` set the archived chats. `folder_id` limits the list to one of the user's folders.
+pub async fn fetch_chats(pool: &PgPool, user_id: i32, query: ChatQuery) -> Result<Vec<ChatResponse>, sqlx::Error> {
+    let chats = sqlx::query!(
+        r#"
+        SELECT
+            c.id,
+            c.is_group,
+            c.user1_id,
+            c.user2_id,
+            u1.display_name AS "user1_display_name?",
+            u1.avatar_path AS "user1_avatar_path?",
+            u1.status_text AS "user1_status_text?",
+            u2.display_name AS "user2_display_name?",
+            u2.avatar_path AS "user2_avatar_path?",
+            u2.status_text AS "user2_status_text?",
+            c.title,
+            c.avatar_path,
+            (SELECT COUNT(*) FROM chat_members members WHERE members.chat_id = c.id AND members.request_status <> 'declined') AS "member_count!",
+            c.created_at,
+            cm.role AS "role: ChatRole",
+            c.last_activity_at,
+            cm.request_status AS "request_status: ChatRequestStatus",
+            cm.muted AND (cm.muted_until IS NULL OR cm.muted_until > NOW()) AS "muted!",
+            cm.muted_until,
+            cm.archived,
+            cm.pinned_position,
+            cm.favourite,
+            cm.last_read_message_id,
+            unread_message_count(c.id, $1) AS "unread_count!",
+            lm.id AS "last_message_id?",
+            lm.content AS "last_message_content?",
+            lm.user_id AS "last_message_sender_id?",
+            lu.display_name AS "last_message_sender_name?",
+            lu.avatar_path AS "last_message_sender_avatar_path?",
+            lu.status_text AS "last_message_sender_status_text?",
+            lm.message_type AS "last_message_type?",
+            lm.created_at AS "last_message_created_at?",
+            lm.deleted_at AS "last_message_deleted_at?"
+        FROM chat_members cm
+        JOIN chats c ON c.id = cm.chat_id
+        LEFT JOIN user_summaries u1 ON c.user1_id = u1.id
+        LEFT JOIN user_summaries u2 ON c.user2_id = u2.id
+        LEFT JOIN messages lm ON lm.id = c.last_message_id
+        LEFT JOIN user_summaries lu ON lu.id = lm.user_id
+        WHERE cm.user_id = $1
+          AND cm.request_status <> 'declined'
+          AND ($2::INTEGER IS NULL OR c.id = $2)
+          AND ($2::INTEGER IS NOT NULL OR (cm.request_status = 'pending') = $3)
+          AND ($2::INTEGER IS NOT NULL OR $3 OR cm.archived = $4)
+          AND ($5::INTEGER IS NULL OR chat_in_folder($5, c.id))
+        ORDER BY cm.pinned_position ASC NULLS LAST, c.last_activity_at DESC, c.id DESC
+        "#,
+        user_id,
+        query.chat_id,
+        query.requests,
+        query.archived,
+        query.folder_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let chat_responses = chats
+        .into_iter()
+        .map(|chat| {
+            let last_message = match (chat.last_message_id, chat.last_message_content, chat.last_message_sender_id, chat.last_message_sender_name) {
+                (Some(id), Some(content), Some(sender_id), Some(sender_name)) => {
+                    let deleted = chat.last_message_deleted_at.is_some();
+
+                    Some(LastMessagePreview {
+                        id,
+                        content: if deleted { String::new() } else { snippet(&content, MESSAGE_SNIPPET_LENGTH) },
+                        sender: UserSummary::new(
+                            sender_id,
+                            sender_name,
+                            chat.last_message_sender_avatar_path,
+                            chat.last_message_sender_status_text,
+                        ),
+                        message_type: chat.last_message_type,
+                        created_at: chat.last_message_created_at,
+                        deleted,
+                    })
+                }
+                _ => None,
+            };
+
+            let user1 = chat.user1_id.zip(chat.user1_display_name).map(|(id, display_name)| {
+                UserSummary::new(id, display_name, chat.user1_avatar_path, chat.user1_status_text)
+            });
+            let user2 = chat.user2_id.zip(chat.user2_display_name).map(|(id, display_name)| {
+                UserSummary::new(id, display_name, chat.user2_avatar_path, chat.user2_status_text)
+            });
+
+            ChatResponse {
+                id: chat.id,
+                is_group: chat.is_group,
+                user1_id: chat.user1_id,
+                user2_id: chat.user2_id,
+                user1,
+                user2,
+                title: chat.title,
+                avatar_url: chat.avatar_path.map(|path| format!("/api/files/{}", path)),
+                member_count: chat.member_count,
+                created_at: chat.created_at,
+                role: chat.role,
+                request_status: chat.request_status,
+                settings: ChatSettings {
+                    muted: chat.muted,
+                    muted_until: if chat.muted { chat.muted_until } else { None },
+                    archived: chat.archived,
+                    pinned_position: chat.pinned_position,
+                    favourite: chat.favourite,
+                },
+                unread_count: chat.unread_count,
+                last_read_message_id: chat.last_read_message_id,
+                last_message,
+                last_activity_at: Some(chat.last_activity_at),
+            }
+        })
+        .collect();
+
+    Ok(chat_responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_users(pool: &PgPool, count: i32) -> Vec<i32> {
+        let run = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos().to_string();
+
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO users (email, password)
+            SELECT 'request-test-' || $1 || '-' || n || '@example.invalid', '!'
+            FROM generate_series(1, $2) AS n
+            RETURNING id
+            "#,
+            run,
+            count
+        )
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn add_member(pool: &PgPool, chat_id: i32, user_id: i32, status: ChatRequestStatus) {
+        sqlx::query!(
+            "INSERT INTO chat_members (chat_id, user_id, request_status) VALUES ($1, $2, $3)",
+            chat_id,
+            user_id,
+            status as ChatRequestStatus
+        )
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn delete_users(pool: &PgPool, chat_id: i32, user_ids: &[i32]) {
+        sqlx::query!("DELETE FROM chats WHERE id = $1", chat_id).execute(pool).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = ANY($1)", user_ids).execute(pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated Postgres database at DATABASE_URL"]
+    async fn requester_gets_one_message_until_the_direct_chat_is_accepted() {
+        let pool = PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        let users = create_users(&pool, 2).await;
+
+        let chat_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO chats (user1_id, user2_id, user1_email, user2_email)
+            SELECT u1.id, u2.id, u1.email, u2.email FROM users u1, users u2 WHERE u1.id = $1 AND u2.id = $2
+            RETURNING id
+            "#,
+            users[0],
+            users[1]
+        )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        add_member(&pool, chat_id, users[0], ChatRequestStatus::Accepted).await;
+        add_member(&pool, chat_id, users[1], ChatRequestStatus::Pending).await;
+
+        assert_eq!(take_request_message(&pool, chat_id, users[0]).await.unwrap(), RequestMessage::Taken);
+        assert_eq!(take_request_message(&pool, chat_id, users[0]).await.unwrap(), RequestMessage::AlreadySent);
+        assert_eq!(take_request_message(&pool, chat_id, users[1]).await.unwrap(), RequestMessage::NotNeeded);
+
+        // Повернене повідомлення можна надіслати знову
+        return_request_message(&pool, chat_id, users[0]).await.unwrap();
+        assert_eq!(take_request_message(&pool, chat_id, users[0]).await.unwrap(), RequestMessage::Taken);
+
+        delete_users(&pool, chat_id, &users).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated Postgres database at DATABASE_URL"]
+    async fn pending_group_members_limit_nobody() {
+        let pool = PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        let users = create_users(&pool, 3).await;
+
+        let chat_id = sqlx::query_scalar!("INSERT INTO chats (is_group, title) VALUES (TRUE, 'Request test') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        add_member(&pool, chat_id, users[0], ChatRequestStatus::Accepted).await;
+        add_member(&pool, chat_id, users[1], ChatRequestStatus::Accepted).await;
+        add_member(&pool, chat_id, users[2], ChatRequestStatus::Pending).await;
+
+        for _ in 0..2 {
+            assert_eq!(take_request_message(&pool, chat_id, users[0]).await.unwrap(), RequestMessage::NotNeeded);
+            assert_eq!(take_request_message(&pool, chat_id, users[1]).await.unwrap(), RequestMessage::NotNeeded);
+        }
+
+        delete_users(&pool, chat_id, &users).await;
+    }
+}
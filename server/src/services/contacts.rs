@@ -0,0 +1,65 @@
+use sqlx::{PgConnection, PgPool};
+use crate::dtos::user::UserSummary;
+
+/// Adds the contact to the user's list. Returns `false` if it was already there.
+pub async fn add_contact(conn: &mut PgConnection, user_id: i32, contact_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO contacts (user_id, contact_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        user_id,
+        contact_id
+    )
+        .execute(conn)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Removes the contact from the user's list. Returns `false` if it was not there.
+pub async fn remove_contact(pool: &PgPool, user_id: i32, contact_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM contacts WHERE user_id = $1 AND contact_id = $2",
+        user_id,
+        contact_id
+    )
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The user's contacts ordered by display name.
+pub async fn fetch_contacts(pool: &PgPool, user_id: i32) -> Result<Vec<UserSummary>, sqlx::Error> {
+    let contacts = sqlx::query!(
+        r#"
+        SELECT
+            s.id AS "id!",
+            s.display_name AS "display_name!",
+            s.avatar_path,
+            s.status_text
+        FROM contacts c
+        JOIN user_summaries s ON s.id = c.contact_id
+        WHERE c.user_id = $1
+        ORDER BY lower(s.display_name), s.id
+        "#,
+        user_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(contacts.into_iter().map(|contact| {
+        UserSummary::new(contact.id, contact.display_name, contact.avatar_path, contact.status_text)
+    }).collect())
+}
+
+/// Whether `contact_id` is in the user's contact list.
+pub async fn is_contact(pool: &PgPool, user_id: i32, contact_id: i32) -> Result<bool, sqlx::Error> {
+    let contact = sqlx::query!(
+        "SELECT contact_id FROM contacts WHERE user_id = $1 AND contact_id = $2",
+        user_id,
+        contact_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(contact.is_some())
+}
@@ -0,0 +1,159 @@
+use sqlx::{PgConnection, PgPool};
+use crate::dtos::folder::ChatFolderResponse;
+
+/// Folders of the user in their order, with unread counts over the chats each one holds.
+/// Archived chats don't count. With `folder_id` set only that folder is returned.
+pub async fn fetch_folders(pool: &PgPool, user_id: i32, folder_id: Option<i32>) -> Result<Vec<ChatFolderResponse>, sqlx::Error> {
+    // Непрочитані рахуються один раз на чат, а правила папок застосовуються до готових рядків
+    let folders = sqlx::query!(
+        r#"
+        WITH member_chats AS (
+            SELECT
+                cm.chat_id,
+                c.is_group,
+                cm.muted AND (cm.muted_until IS NULL OR cm.muted_until > NOW()) AS muted,
+                (
+                    SELECT COUNT(*)
+                    FROM messages m
+                    WHERE m.chat_id = cm.chat_id
+                      AND m.id > COALESCE(cm.last_read_message_id, 0)
+                      AND m.user_id <> cm.user_id
+                      AND m.deleted_at IS NULL
+                      AND NOT EXISTS (
+                          SELECT 1 FROM user_blocks b
+                          WHERE b.blocker_id = cm.user_id AND b.blocked_id = m.user_id AND b.created_at <= m.created_at
+                      )
+                ) AS unread
+            FROM chat_members cm
+            JOIN chats c ON c.id = cm.chat_id
+            WHERE cm.user_id = $1
+              AND cm.request_status = 'accepted'
+              AND NOT cm.archived
+        ),
+        counts AS (
+            SELECT
+                f.id AS folder_id,
+                SUM(mc.unread)::BIGINT AS unread_count,
+                COUNT(*) FILTER (WHERE mc.unread > 0) AS unread_chats
+            FROM chat_folders f
+            JOIN member_chats mc ON NOT (f.exclude_muted AND mc.muted)
+            WHERE f.user_id = $1
+              AND ($2::INTEGER IS NULL OR f.id = $2)
+              AND (
+                  EXISTS (SELECT 1 FROM chat_folder_chats fc WHERE fc.folder_id = f.id AND fc.chat_id = mc.chat_id)
+                  OR (f.include_unread AND mc.unread > 0)
+                  OR (f.include_groups AND mc.is_group)
+                  OR (f.include_direct AND NOT mc.is_group)
+                  OR EXISTS (
+                      SELECT 1
+                      FROM chat_folder_contacts fu
+                      JOIN chat_members other ON other.chat_id = mc.chat_id AND other.user_id = fu.user_id
+                      WHERE fu.folder_id = f.id
+                  )
+              )
+            GROUP BY f.id
+        )
+        SELECT
+            f.id,
+            f.name,
+            f.position,
+            f.include_unread,
+            f.include_groups,
+            f.include_direct,
+            f.exclude_muted,
+            ARRAY(SELECT fc.chat_id FROM chat_folder_chats fc WHERE fc.folder_id = f.id ORDER BY fc.chat_id) AS "chat_ids!",
+            ARRAY(SELECT fu.user_id FROM chat_folder_contacts fu WHERE fu.folder_id = f.id ORDER BY fu.user_id) AS "contact_ids!",
+            COALESCE(counts.unread_count, 0) AS "unread_count!",
+            COALESCE(counts.unread_chats, 0) AS "unread_chats!"
+        FROM chat_folders f
+        LEFT JOIN counts ON counts.folder_id = f.id
+        WHERE f.user_id = $1
+          AND ($2::INTEGER IS NULL OR f.id = $2)
+        ORDER BY f.position ASC, f.id ASC
+        "#,
+        user_id,
+        folder_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(folders.into_iter().map(|folder| ChatFolderResponse {
+        id: folder.id,
+        name: folder.name,
+        position: folder.position,
+        include_unread: folder.include_unread,
+        include_groups: folder.include_groups,
+        include_direct: folder.include_direct,
+        exclude_muted: folder.exclude_muted,
+        chat_ids: folder.chat_ids,
+        contact_ids: folder.contact_ids,
+        unread_count: folder.unread_count,
+        unread_chats: folder.unread_chats,
+    }).collect())
+}
+
+pub async fn folder_exists(pool: &PgPool, user_id: i32, folder_id: i32) -> Result<bool, sqlx::Error> {
+    let folder = sqlx::query!(
+        "SELECT id FROM chat_folders WHERE id = $1 AND user_id = $2",
+        folder_id,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(folder.is_some())
+}
+
+/// Whether the user is a member of every listed chat.
+pub async fn are_own_chats(pool: &PgPool, user_id: i32, chat_ids: &[i32]) -> Result<bool, sqlx::Error> {
+    let chats = sqlx::query!(
+        r#"SELECT COUNT(DISTINCT chat_id) AS "count!" FROM chat_members WHERE user_id = $1 AND chat_id = ANY($2)"#,
+        user_id,
+        chat_ids
+    )
+        .fetch_one(pool)
+        .await?;
+
+    let mut unique = chat_ids.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+
+    Ok(chats.count == unique.len() as i64)
+}
+
+pub async fn replace_folder_chats(conn: &mut PgConnection, folder_id: i32, chat_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM chat_folder_chats WHERE folder_id = $1", folder_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO chat_folder_chats (folder_id, chat_id) SELECT $1, UNNEST($2::INTEGER[]) ON CONFLICT DO NOTHING",
+        folder_id,
+        chat_ids
+    )
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Unknown user ids are skipped.
+pub async fn replace_folder_contacts(conn: &mut PgConnection, folder_id: i32, user_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM chat_folder_contacts WHERE folder_id = $1", folder_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO chat_folder_contacts (folder_id, user_id)
+        SELECT $1, u.id FROM users u WHERE u.id = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        folder_id,
+        user_ids
+    )
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
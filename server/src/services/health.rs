@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+use std::path::Path;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+
+/// Migrations embedded at build time. Applied on startup and compared against the database by `/readyz`.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+const STORAGE_PROBE_FILE: &str = ".readyz-probe";
+
+pub async fn check_database(pool: &PgPool) -> Result<(), &'static str> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            tracing::warn!(error = %e, "Readiness: database is unreachable");
+            "database is unreachable"
+        })
+}
+
+/// Every migration this build ships with must have been applied successfully.
+pub async fn check_migrations(pool: &PgPool) -> Result<(), &'static str> {
+    // Таблицю веде сам sqlx, тому запит не перевіряється макросом
+    let applied: HashSet<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            tracing::warn!(error = %e, "Readiness: failed to read applied migrations");
+            "failed to read applied migrations"
+        })?
+        .into_iter()
+        .collect();
+
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .count();
+
+    if pending == 0 {
+        Ok(())
+    } else {
+        tracing::warn!(pending, "Readiness: migrations are pending");
+        Err("migrations are pending")
+    }
+}
+
+/// Writes and removes a probe file, so a read-only or full volume shows up before uploads fail.
+pub async fn check_storage(directory: &Path) -> Result<(), &'static str> {
+    let probe = directory.join(STORAGE_PROBE_FILE);
+
+    let result = match tokio::fs::write(&probe, b"ok").await {
+        Ok(()) => tokio::fs::remove_file(&probe).await,
+        Err(e) => Err(e),
+    };
+
+    result.map_err(|e| {
+        tracing::warn!(error = %e, "Readiness: upload directory is not writable");
+        "upload directory is not writable"
+    })
+}
@@ -0,0 +1,107 @@
+use sqlx::{PgConnection, PgPool};
+use crate::dtos::invite::{InviteResponse, InviteUseResponse, InviteUseStatus};
+use crate::dtos::user::UserSummary;
+
+/// Every invite link of the chat, newest first, including expired and revoked ones.
+pub async fn fetch_invites(pool: &PgPool, chat_id: i32, invite_id: Option<i32>) -> Result<Vec<InviteResponse>, sqlx::Error> {
+    let invites = sqlx::query!(
+        r#"
+        SELECT id, chat_id, token, created_by, created_at, expires_at, max_uses, use_count, requires_approval, revoked_at
+        FROM chat_invites
+        WHERE chat_id = $1 AND ($2::INTEGER IS NULL OR id = $2)
+        ORDER BY created_at DESC, id DESC
+        "#,
+        chat_id,
+        invite_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(invites.into_iter().map(|invite| InviteResponse {
+        id: invite.id,
+        chat_id: invite.chat_id,
+        token: invite.token,
+        created_by: invite.created_by,
+        created_at: invite.created_at,
+        expires_at: invite.expires_at,
+        max_uses: invite.max_uses,
+        use_count: invite.use_count,
+        requires_approval: invite.requires_approval,
+        revoked_at: invite.revoked_at,
+    }).collect())
+}
+
+/// Who used the chat's invite links, newest first, optionally narrowed to one link or status.
+pub async fn fetch_invite_uses(
+    pool: &PgPool,
+    chat_id: i32,
+    invite_id: Option<i32>,
+    status: Option<InviteUseStatus>,
+) -> Result<Vec<InviteUseResponse>, sqlx::Error> {
+    let uses = sqlx::query!(
+        r#"
+        SELECT
+            u.id, u.invite_id, u.used_at, u.reviewed_by, u.reviewed_at,
+            u.status AS "status: InviteUseStatus",
+            s.id AS "user_id!",
+            s.display_name AS "display_name!",
+            s.avatar_path,
+            s.status_text
+        FROM chat_invite_uses u
+        JOIN chat_invites i ON i.id = u.invite_id
+        JOIN user_summaries s ON s.id = u.user_id
+        WHERE i.chat_id = $1
+          AND ($2::INTEGER IS NULL OR u.invite_id = $2)
+          AND ($3::invite_use_status IS NULL OR u.status = $3)
+        ORDER BY u.used_at DESC, u.id DESC
+        "#,
+        chat_id,
+        invite_id,
+        status as Option<InviteUseStatus>
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(uses.into_iter().map(|invite_use| InviteUseResponse {
+        id: invite_use.id,
+        invite_id: invite_use.invite_id,
+        user: UserSummary::new(invite_use.user_id, invite_use.display_name, invite_use.avatar_path, invite_use.status_text),
+        status: invite_use.status,
+        used_at: invite_use.used_at,
+        reviewed_by: invite_use.reviewed_by,
+        reviewed_at: invite_use.reviewed_at,
+    }).collect())
+}
+
+/// Makes the user an active member. Someone who was added earlier and has not answered yet,
+/// or who declined the chat, joins as a plain member. Returns false if they already were one.
+pub async fn join_chat(conn: &mut PgConnection, chat_id: i32, user_id: i32) -> Result<bool, sqlx::Error> {
+    let joined = sqlx::query!(
+        r#"
+        INSERT INTO chat_members (chat_id, user_id, role, request_status)
+        VALUES ($1, $2, 'member', 'accepted')
+        ON CONFLICT (chat_id, user_id) DO UPDATE
+        SET role = 'member', request_status = 'accepted', joined_at = NOW()
+        WHERE chat_members.request_status <> 'accepted'
+        RETURNING user_id
+        "#,
+        chat_id,
+        user_id
+    )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    Ok(joined.is_some())
+}
+
+/// Members who review join requests.
+pub async fn chat_admin_ids(pool: &PgPool, chat_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    let admins = sqlx::query!(
+        "SELECT user_id FROM chat_members WHERE chat_id = $1 AND role IN ('owner', 'admin') AND request_status = 'accepted'",
+        chat_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(admins.into_iter().map(|admin| admin.user_id).collect())
+}
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+use std::time::Instant;
+use sqlx::PgPool;
+use tokio::sync::{mpsc, oneshot};
+use crate::config::PersistenceConfig;
+use crate::metrics::METRICS;
+
+/// A chat message to store, already checked against the sender's permissions.
+pub struct NewMessage {
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub content: String,
+    pub file_path: Option<String>,
+    /// Whether the server stored `file_path` itself for this message.
+    pub file_uploaded: bool,
+    pub message_type: String,
+    pub reply_to_message_id: Option<i32>,
+    pub thread_root_id: Option<i32>,
+}
+
+/// Runs on the writer right after the message is committed, with its id.
+pub type OnCommit = Box<dyn FnOnce(i32) + Send>;
+
+pub enum WriteError {
+    Stopped,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::Stopped => f.write_str("message writer stopped"),
+            WriteError::Database(error) => error.fmt(f),
+        }
+    }
+}
+
+struct Job {
+    message: NewMessage,
+    on_commit: OnCommit,
+    queued_at: Instant,
+    done: oneshot::Sender<Result<i32, WriteError>>,
+}
+
+/// Stores chat messages in batches. Each chat is always handled by the same writer, which
+/// commits and runs `on_commit` callbacks in the order the messages were queued, so messages
+/// of one chat keep their order in the database and on the wire.
+#[derive(Clone)]
+pub struct MessageWriter {
+    writers: Arc<[mpsc::Sender<Job>]>,
+}
+
+impl MessageWriter {
+    /// Spawns `config.writers` writer tasks.
+    pub fn start(pool: PgPool, config: &PersistenceConfig) -> Self {
+        let writers: Arc<[mpsc::Sender<Job>]> = (0..config.writers)
+            .map(|_| {
+                let (sender, jobs) = mpsc::channel(config.queue_capacity);
+                tokio::spawn(run_writer(pool.clone(), jobs, config.batch_size));
+                sender
+            })
+            .collect();
+
+        MessageWriter { writers }
+    }
+
+    /// Queues the message and waits until it is committed. Waits for room in the queue first
+    /// when the database falls behind, which in turn stops reading from the sender's socket.
+    pub async fn save(&self, message: NewMessage, on_commit: OnCommit) -> Result<i32, WriteError> {
+        let writer = &self.writers[message.chat_id.unsigned_abs() as usize % self.writers.len()];
+        let (done, result) = oneshot::channel();
+        let job = Job { message, on_commit, queued_at: Instant::now(), done };
+
+        METRICS.message_write_queue.inc();
+        if writer.send(job).await.is_err() {
+            METRICS.message_write_queue.dec();
+            return Err(WriteError::Stopped);
+        }
+
+        result.await.unwrap_or(Err(WriteError::Stopped))
+    }
+}
+
+async fn run_writer(pool: PgPool, mut jobs: mpsc::Receiver<Job>, batch_size: usize) {
+    let mut batch = Vec::with_capacity(batch_size);
+
+    // Чекаємо на перше повідомлення й забираємо все, що накопичилось за час попереднього запису
+    while jobs.recv_many(&mut batch, batch_size).await > 0 {
+        METRICS.message_write_queue.sub(batch.len() as i64);
+        write_batch(&pool, std::mem::take(&mut batch)).await;
+    }
+}
+
+async fn write_batch(pool: &PgPool, batch: Vec<Job>) {
+    let messages: Vec<&NewMessage> = batch.iter().map(|job| &job.message).collect();
+
+    match insert_messages(pool, &messages).await {
+        Ok(ids) => commit(batch, ids),
+        Err(e) if batch.len() == 1 => {
+            if let Some(job) = batch.into_iter().next() {
+                fail(job, e);
+            }
+        }
+        Err(e) => {
+            // Одне невдале повідомлення, напр. відповідь на щойно видалене, не має губити решту
+            tracing::warn!(error = %e, messages = batch.len(), "Failed to store message batch, storing one by one");
+
+            for job in batch {
+                match insert_messages(pool, &[&job.message]).await {
+                    Ok(ids) => commit(vec![job], ids),
+                    Err(e) => fail(job, e),
+                }
+            }
+        }
+    }
+}
+
+fn commit(batch: Vec<Job>, ids: Vec<i32>) {
+    METRICS.chat_messages.inc_by(batch.len() as u64);
+    METRICS.message_batch_size.observe(batch.len() as f64);
+
+    for (job, id) in batch.into_iter().zip(ids) {
+        METRICS.message_commit_duration.observe(job.queued_at.elapsed().as_secs_f64());
+        tracing::debug!(message_id = id, chat_id = job.message.chat_id, "Message saved");
+
+        (job.on_commit)(id);
+        let _ = job.done.send(Ok(id));
+    }
+}
+
+fn fail(job: Job, error: sqlx::Error) {
+    tracing::error!(error = %error, chat_id = job.message.chat_id, "Failed to store message");
+    let _ = job.done.send(Err(WriteError::Database(error)));
+}
+
+/// Inserts the messages in one transaction, together with the last message and activity of
+/// their chats and the reply counters of their threads. Returns the ids in the given order.
+async fn insert_messages(pool: &PgPool, messages: &[&NewMessage]) -> Result<Vec<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Id беремо з послідовності наперед: так вони зростають у порядку черги, а не як вирішить INSERT
+    let mut ids = sqlx::query_scalar!(
+        r#"SELECT nextval(pg_get_serial_sequence('messages', 'id'))::INT AS "id!" FROM generate_series(1, $1)"#,
+        messages.len() as i32
+    )
+        .fetch_all(&mut *tx)
+        .await?;
+    ids.sort_unstable();
+
+    let chat_ids: Vec<i32> = messages.iter().map(|message| message.chat_id).collect();
+    let user_ids: Vec<i32> = messages.iter().map(|message| message.user_id).collect();
+    let contents: Vec<String> = messages.iter().map(|message| message.content.clone()).collect();
+    let file_paths: Vec<Option<String>> = messages.iter().map(|message| message.file_path.clone()).collect();
+    let files_uploaded: Vec<bool> = messages.iter().map(|message| message.file_uploaded).collect();
+    let message_types: Vec<String> = messages.iter().map(|message| message.message_type.clone()).collect();
+    let reply_to_ids: Vec<Option<i32>> = messages.iter().map(|message| message.reply_to_message_id).collect();
+    let thread_root_ids: Vec<Option<i32>> = messages.iter().map(|message| message.thread_root_id).collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO messages (id, chat_id, user_id, content, created_at, file_path, file_uploaded, message_type, reply_to_message_id, thread_root_id)
+        SELECT id, chat_id, user_id, content, NOW(), file_path, file_uploaded, message_type, reply_to_message_id, thread_root_id
+        FROM UNNEST($1::INT[], $2::INT[], $3::INT[], $4::TEXT[], $5::TEXT[], $6::BOOL[], $7::TEXT[], $8::INT[], $9::INT[])
+            AS batch (id, chat_id, user_id, content, file_path, file_uploaded, message_type, reply_to_message_id, thread_root_id)
+        "#,
+        &ids,
+        &chat_ids,
+        &user_ids,
+        &contents,
+        &file_paths as &[Option<String>],
+        &files_uploaded,
+        &message_types,
+        &reply_to_ids as &[Option<i32>],
+        &thread_root_ids as &[Option<i32>]
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE messages
+        SET reply_count = reply_count + replies.count,
+            last_reply_at = NOW()
+        FROM (
+            SELECT thread_root_id, COUNT(*)::INT AS count
+            FROM UNNEST($1::INT[]) AS batch (thread_root_id)
+            WHERE thread_root_id IS NOT NULL
+            GROUP BY thread_root_id
+        ) replies
+        WHERE messages.id = replies.thread_root_id
+        "#,
+        &thread_root_ids as &[Option<i32>]
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    // Відповіді в гілках не стають останнім повідомленням чату, але оновлюють його активність
+    sqlx::query!(
+        r#"
+        UPDATE chats
+        SET last_message_id = COALESCE(latest.message_id, chats.last_message_id),
+            last_activity_at = NOW()
+        FROM (
+            SELECT chat_id, MAX(id) FILTER (WHERE thread_root_id IS NULL) AS message_id
+            FROM UNNEST($1::INT[], $2::INT[], $3::INT[]) AS batch (id, chat_id, thread_root_id)
+            GROUP BY chat_id
+        ) latest
+        WHERE chats.id = latest.chat_id
+        "#,
+        &ids,
+        &chat_ids,
+        &thread_root_ids as &[Option<i32>]
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(ids)
+}
@@ -0,0 +1,170 @@
+use sqlx::PgPool;
+use std::path::Path;
+use crate::constants::common::MESSAGE_SNIPPET_LENGTH;
+use crate::dtos::chat::{MessageResponse, ReplyPreview};
+use crate::dtos::user::UserSummary;
+use crate::services::reactions::fetch_reactions;
+use crate::services::receipts::{message_status, others_cursors};
+use crate::utils::text::snippet;
+
+/// Narrows down which messages of a chat `fetch_messages` returns.
+/// By default that is the main timeline, i.e. everything except thread replies.
+#[derive(Default)]
+pub struct MessageQuery {
+    pub message_id: Option<i32>,
+    pub thread_root_id: Option<i32>,
+    pub after_id: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+/// Messages of a chat as the viewer sees them, oldest first: messages the viewer
+/// deleted for themselves or received from a user they had blocked are left out
+/// and messages deleted for everyone are blanked.
+pub async fn fetch_messages(
+    pool: &PgPool,
+    viewer_id: i32,
+    chat_id: i32,
+    query: MessageQuery,
+) -> Result<Vec<MessageResponse>, sqlx::Error> {
+    let messages = sqlx::query!(
+        r#"
+        SELECT
+            m.id, m.chat_id, m.user_id, m.content, m.created_at, m.file_path, m.message_type,
+            m.edited_at, m.deleted_at, m.reply_to_message_id, m.thread_root_id, m.reply_count, m.last_reply_at,
+            sender.display_name AS "sender_display_name!",
+            sender.avatar_path AS sender_avatar_path,
+            sender.status_text AS sender_status_text,
+            r.user_id AS "reply_user_id?",
+            r.content AS "reply_content?",
+            r.message_type AS "reply_message_type?",
+            r.deleted_at AS "reply_deleted_at?",
+            p.pinned_at AS "pinned_at?"
+        FROM messages m
+        JOIN user_summaries sender ON sender.id = m.user_id
+        LEFT JOIN messages r ON r.id = m.reply_to_message_id
+        LEFT JOIN pinned_messages p ON p.message_id = m.id
+        WHERE m.chat_id = $1
+          AND ($3::INTEGER IS NULL OR m.id = $3)
+          AND (
+              $3::INTEGER IS NOT NULL
+              OR ($4::INTEGER IS NULL AND m.thread_root_id IS NULL)
+              OR m.thread_root_id = $4
+          )
+          AND ($5::INTEGER IS NULL OR m.id > $5)
+          AND NOT EXISTS (SELECT 1 FROM hidden_messages h WHERE h.message_id = m.id AND h.user_id = $2)
+          AND NOT EXISTS (
+              SELECT 1 FROM user_blocks b
+              WHERE b.blocker_id = $2 AND b.blocked_id = m.user_id AND b.created_at <= m.created_at
+          )
+        ORDER BY m.id ASC
+        LIMIT $6
+        "#,
+        chat_id,
+        viewer_id,
+        query.message_id,
+        query.thread_root_id,
+        query.after_id,
+        query.limit
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let cursors = others_cursors(pool, chat_id, viewer_id).await?;
+    let message_ids: Vec<i32> = messages.iter().map(|msg| msg.id).collect();
+    let mut reactions = fetch_reactions(pool, viewer_id, &message_ids).await?;
+
+    let response = messages.into_iter().map(|msg| {
+        let deleted = msg.deleted_at.is_some();
+
+        let reply_to = match (msg.reply_to_message_id, msg.reply_user_id, msg.reply_content) {
+            (Some(message_id), Some(user_id), Some(content)) => Some(reply_preview(
+                message_id,
+                user_id,
+                &content,
+                msg.reply_message_type,
+                msg.reply_deleted_at.is_some(),
+            )),
+            _ => None,
+        };
+
+        MessageResponse {
+            status: message_status(msg.id, msg.user_id, viewer_id, &cursors),
+            id: msg.id,
+            chat_id: msg.chat_id,
+            user_id: msg.user_id,
+            sender: UserSummary::new(msg.user_id, msg.sender_display_name, msg.sender_avatar_path, msg.sender_status_text),
+            content: if deleted { String::new() } else { msg.content },
+            created_at: msg.created_at,
+            file_path: if deleted { None } else { msg.file_path },
+            message_type: msg.message_type,
+            edited_at: msg.edited_at,
+            deleted_at: msg.deleted_at,
+            reply_to_message_id: msg.reply_to_message_id,
+            reply_to,
+            thread_root_id: msg.thread_root_id,
+            reply_count: msg.reply_count,
+            last_reply_at: msg.last_reply_at,
+            reactions: if deleted { Vec::new() } else { reactions.remove(&msg.id).unwrap_or_default() },
+            pinned_at: msg.pinned_at,
+        }
+    }).collect();
+
+    Ok(response)
+}
+
+fn reply_preview(message_id: i32, user_id: i32, content: &str, message_type: Option<String>, deleted: bool) -> ReplyPreview {
+    ReplyPreview {
+        message_id,
+        user_id,
+        content: if deleted { String::new() } else { snippet(content, MESSAGE_SNIPPET_LENGTH) },
+        message_type,
+        deleted,
+    }
+}
+
+/// Quoted preview of a message, if it exists in the given chat.
+pub async fn fetch_reply_preview(pool: &PgPool, chat_id: i32, message_id: i32) -> Result<Option<ReplyPreview>, sqlx::Error> {
+    let message = sqlx::query!(
+        "SELECT id, user_id, content, message_type, deleted_at FROM messages WHERE id = $1 AND chat_id = $2",
+        message_id,
+        chat_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(message.map(|message| reply_preview(
+        message.id,
+        message.user_id,
+        &message.content,
+        message.message_type,
+        message.deleted_at.is_some(),
+    )))
+}
+
+/// Whether the message can be the root of a thread in the given chat.
+/// Replies inside a thread can't start threads of their own.
+pub async fn is_thread_root(pool: &PgPool, chat_id: i32, message_id: i32) -> Result<bool, sqlx::Error> {
+    let root = sqlx::query!(
+        "SELECT id FROM messages WHERE id = $1 AND chat_id = $2 AND thread_root_id IS NULL AND deleted_at IS NULL",
+        message_id,
+        chat_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(root.is_some())
+}
+
+/// Whether another message still points to the stored file. Paths are compared by file name,
+/// as files are served by name; avatars are named by the server and never share a message file's name.
+pub async fn is_file_referenced(pool: &PgPool, file_path: &str, message_id: i32) -> Result<bool, sqlx::Error> {
+    let file_name = Path::new(file_path).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM messages WHERE file_name = $1 AND id <> $2) AS "referenced!""#,
+        file_name,
+        message_id
+    )
+        .fetch_one(pool)
+        .await
+}
@@ -0,0 +1,13 @@
+pub mod blocks;
+pub mod chats;
+pub mod contacts;
+pub mod folders;
+pub mod invites;
+pub mod messages;
+pub mod permissions;
+pub mod reactions;
+pub mod receipts;
+pub mod search;
+pub mod users;
+pub mod health;
+pub mod message_writer;
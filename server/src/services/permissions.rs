@@ -0,0 +1,139 @@
+use sqlx::PgPool;
+use crate::dtos::chat::{ChatRequestStatus, ChatRole};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChatPermission {
+    Post,
+    SendFiles,
+    AddMembers,
+    RemoveMembers,
+    ChangeRoles,
+    EditChatInfo,
+    PinMessages,
+    DeleteOthersMessages,
+    ManageInvites,
+}
+
+/// A user's membership in a chat, as far as permissions are concerned.
+#[derive(Clone, Copy, Debug)]
+pub struct MemberAccess {
+    pub role: ChatRole,
+    pub is_group: bool,
+    pub pending: bool,  // Added to the chat but hasn't accepted it yet
+}
+
+impl MemberAccess {
+    /// The permission matrix. Members who haven't accepted the chat may do nothing until they do.
+    /// Both members of a direct chat may post, send files and pin messages; the rest only makes
+    /// sense in groups.
+    pub fn can(&self, permission: ChatPermission) -> bool {
+        use ChatPermission::*;
+
+        if self.pending {
+            return false;
+        }
+
+        if !self.is_group {
+            return matches!(permission, Post | SendFiles | PinMessages);
+        }
+
+        match self.role {
+            ChatRole::Owner | ChatRole::Admin => true,
+            ChatRole::Member => matches!(permission, Post | SendFiles | AddMembers),
+            ChatRole::ReadOnly => false,
+        }
+    }
+}
+
+/// Who outranks whom when removing members or changing roles.
+pub fn role_rank(role: ChatRole) -> u8 {
+    match role {
+        ChatRole::Owner => 3,
+        ChatRole::Admin => 2,
+        ChatRole::Member | ChatRole::ReadOnly => 1,
+    }
+}
+
+/// The user's access to the chat, if they are a member who has not declined it.
+pub async fn member_access(pool: &PgPool, chat_id: i32, user_id: i32) -> Result<Option<MemberAccess>, sqlx::Error> {
+    let member = sqlx::query!(
+        r#"
+        SELECT cm.role AS "role: ChatRole", cm.request_status AS "request_status: ChatRequestStatus", c.is_group
+        FROM chat_members cm
+        JOIN chats c ON c.id = cm.chat_id
+        WHERE cm.chat_id = $1 AND cm.user_id = $2 AND cm.request_status <> 'declined'
+        "#,
+        chat_id,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(member.map(|member| MemberAccess {
+        role: member.role,
+        is_group: member.is_group,
+        pending: member.request_status == ChatRequestStatus::Pending,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ChatPermission::*;
+
+    const ACTIONS: [ChatPermission; 9] = [
+        Post, SendFiles, AddMembers, RemoveMembers, ChangeRoles, EditChatInfo, PinMessages, DeleteOthersMessages, ManageInvites,
+    ];
+
+    #[test]
+    fn group_permissions_by_role() {
+        // Дозволи для кожної дії в порядку ACTIONS
+        let matrix = [
+            (ChatRole::Owner, [true, true, true, true, true, true, true, true, true]),
+            (ChatRole::Admin, [true, true, true, true, true, true, true, true, true]),
+            (ChatRole::Member, [true, true, true, false, false, false, false, false, false]),
+            (ChatRole::ReadOnly, [false, false, false, false, false, false, false, false, false]),
+        ];
+
+        for (role, allowed) in matrix {
+            let access = MemberAccess { role, is_group: true, pending: false };
+
+            for (action, allowed) in ACTIONS.into_iter().zip(allowed) {
+                assert_eq!(access.can(action), allowed, "{:?} in a group, {:?}", role, action);
+            }
+        }
+    }
+
+    #[test]
+    fn direct_chat_permissions_ignore_the_role() {
+        let allowed = [true, true, false, false, false, false, true, false, false];
+
+        for role in [ChatRole::Owner, ChatRole::Admin, ChatRole::Member, ChatRole::ReadOnly] {
+            let access = MemberAccess { role, is_group: false, pending: false };
+
+            for (action, allowed) in ACTIONS.into_iter().zip(allowed) {
+                assert_eq!(access.can(action), allowed, "{:?} in a direct chat, {:?}", role, action);
+            }
+        }
+    }
+
+    #[test]
+    fn pending_members_may_do_nothing() {
+        for role in [ChatRole::Owner, ChatRole::Admin, ChatRole::Member, ChatRole::ReadOnly] {
+            for is_group in [true, false] {
+                let access = MemberAccess { role, is_group, pending: true };
+
+                for action in ACTIONS {
+                    assert!(!access.can(action), "pending {:?}, group: {}, {:?}", role, is_group, action);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn owners_outrank_admins_who_outrank_everyone_else() {
+        assert!(role_rank(ChatRole::Owner) > role_rank(ChatRole::Admin));
+        assert!(role_rank(ChatRole::Admin) > role_rank(ChatRole::Member));
+        assert_eq!(role_rank(ChatRole::Member), role_rank(ChatRole::ReadOnly));
+    }
+}
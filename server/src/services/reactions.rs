@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use sqlx::PgPool;
+use crate::dtos::chat::ReactionSummary;
+use crate::services::chats::chat_member_ids;
+use crate::utils::validators::is_reaction_emoji;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+
+#[derive(Debug)]
+pub enum ReactionError {
+    InvalidEmoji,
+    MessageNotFound,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for ReactionError {
+    fn from(error: sqlx::Error) -> Self {
+        ReactionError::Database(error)
+    }
+}
+
+/// Reaction summaries of the given messages, keyed by message id, in the order the
+/// emoji were first used.
+pub async fn fetch_reactions(pool: &PgPool, viewer_id: i32, message_ids: &[i32]) -> Result<HashMap<i32, Vec<ReactionSummary>>, sqlx::Error> {
+    let reactions = sqlx::query!(
+        r#"
+        SELECT
+            message_id,
+            emoji,
+            COUNT(*) AS "count!",
+            BOOL_OR(user_id = $2) AS "reacted!"
+        FROM message_reactions
+        WHERE message_id = ANY($1)
+        GROUP BY message_id, emoji
+        ORDER BY MIN(created_at) ASC
+        "#,
+        message_ids,
+        viewer_id
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut summaries: HashMap<i32, Vec<ReactionSummary>> = HashMap::new();
+
+    for reaction in reactions {
+        summaries.entry(reaction.message_id).or_default().push(ReactionSummary {
+            emoji: reaction.emoji,
+            count: reaction.count,
+            reacted: reaction.reacted,
+        });
+    }
+
+    Ok(summaries)
+}
+
+/// Adds or removes the user's reaction and tells the chat members about it.
+/// Membership of the user has to be checked by the caller.
+pub async fn set_reaction(
+    pool: &PgPool,
+    hub: &Hub,
+    chat_id: i32,
+    message_id: i32,
+    user_id: i32,
+    emoji: &str,
+    reacted: bool,
+) -> Result<Vec<ReactionSummary>, ReactionError> {
+    if !is_reaction_emoji(emoji) {
+        return Err(ReactionError::InvalidEmoji);
+    }
+
+    let message = sqlx::query!(
+        "SELECT id FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL",
+        message_id,
+        chat_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    if message.is_none() {
+        return Err(ReactionError::MessageNotFound);
+    }
+
+    let changed = if reacted {
+        sqlx::query!(
+            "INSERT INTO message_reactions (message_id, user_id, emoji) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            message_id,
+            user_id,
+            emoji
+        )
+            .execute(pool)
+            .await?
+            .rows_affected() > 0
+    } else {
+        sqlx::query!(
+            "DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+            message_id,
+            user_id,
+            emoji
+        )
+            .execute(pool)
+            .await?
+            .rows_affected() > 0
+    };
+
+    if changed {
+        let event = if reacted {
+            ServerEvent::ReactionAdded { chat_id, message_id, user_id, emoji: emoji.to_string() }
+        } else {
+            ServerEvent::ReactionRemoved { chat_id, message_id, user_id, emoji: emoji.to_string() }
+        };
+        let members = chat_member_ids(pool, chat_id).await?;
+        hub.send_to_users(&members, &event.to_message());
+    }
+
+    let mut summaries = fetch_reactions(pool, user_id, &[message_id]).await?;
+    Ok(summaries.remove(&message_id).unwrap_or_default())
+}
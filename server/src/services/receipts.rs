@@ -0,0 +1,119 @@
+use sqlx::PgPool;
+use crate::dtos::chat::MessageStatus;
+use crate::services::chats::chat_member_ids;
+use crate::websockets::events::ServerEvent;
+use crate::websockets::hub::Hub;
+
+/// How far every member other than the viewer has received and read a chat.
+pub struct ReceiptCursors {
+    pub delivered_up_to: i32,
+    pub read_up_to: i32,
+}
+
+pub async fn others_cursors(pool: &PgPool, chat_id: i32, viewer_id: i32) -> Result<ReceiptCursors, sqlx::Error> {
+    let cursors = sqlx::query!(
+        r#"
+        SELECT
+            MIN(COALESCE(last_delivered_message_id, 0)) AS delivered_up_to,
+            MIN(COALESCE(last_read_message_id, 0)) AS read_up_to
+        FROM chat_members
+        WHERE chat_id = $1 AND user_id <> $2
+        "#,
+        chat_id,
+        viewer_id
+    )
+        .fetch_one(pool)
+        .await?;
+
+    Ok(ReceiptCursors {
+        delivered_up_to: cursors.delivered_up_to.unwrap_or(0),
+        read_up_to: cursors.read_up_to.unwrap_or(0),
+    })
+}
+
+/// Delivery state of a message as seen by the viewer. Only the author's own messages have one.
+pub fn message_status(message_id: i32, author_id: i32, viewer_id: i32, cursors: &ReceiptCursors) -> Option<MessageStatus> {
+    if author_id != viewer_id {
+        return None;
+    }
+
+    if cursors.read_up_to >= message_id {
+        Some(MessageStatus::Read)
+    } else if cursors.delivered_up_to >= message_id {
+        Some(MessageStatus::Delivered)
+    } else {
+        Some(MessageStatus::Sent)
+    }
+}
+
+/// Moves the member's read cursor forward and tells the other members.
+/// Returns `false` if the cursor did not move (unknown message, not a member or already read).
+pub async fn mark_read(pool: &PgPool, hub: &Hub, chat_id: i32, user_id: i32, message_id: i32) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chat_members
+        SET last_read_message_id = $3,
+            last_delivered_message_id = GREATEST(COALESCE(last_delivered_message_id, 0), $3)
+        WHERE chat_id = $1
+          AND user_id = $2
+          AND COALESCE(last_read_message_id, 0) < $3
+          AND EXISTS (SELECT 1 FROM messages WHERE id = $3 AND chat_id = $1)
+        RETURNING user_id
+        "#,
+        chat_id,
+        user_id,
+        message_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    if updated.is_none() {
+        return Ok(false);
+    }
+
+    let recipients: Vec<i32> = chat_member_ids(pool, chat_id)
+        .await?
+        .into_iter()
+        .filter(|member| *member != user_id)
+        .collect();
+
+    let event = ServerEvent::MessagesRead { chat_id, user_id, message_id };
+    hub.send_to_users(&recipients, &event.to_message());
+
+    Ok(true)
+}
+
+/// Moves the member's delivery cursor forward and tells the other members.
+pub async fn mark_delivered(pool: &PgPool, hub: &Hub, chat_id: i32, user_id: i32, message_id: i32) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE chat_members
+        SET last_delivered_message_id = $3
+        WHERE chat_id = $1
+          AND user_id = $2
+          AND COALESCE(last_delivered_message_id, 0) < $3
+          AND EXISTS (SELECT 1 FROM messages WHERE id = $3 AND chat_id = $1)
+        RETURNING user_id
+        "#,
+        chat_id,
+        user_id,
+        message_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    if updated.is_none() {
+        return Ok(false);
+    }
+
+    let recipients: Vec<i32> = chat_member_ids(pool, chat_id)
+        .await?
+        .into_iter()
+        .filter(|member| *member != user_id)
+        .collect();
+
+    let event = ServerEvent::MessagesDelivered { chat_id, user_id, message_id };
+    hub.send_to_users(&recipients, &event.to_message());
+
+    Ok(true)
+}
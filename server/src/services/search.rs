@@ -0,0 +1,180 @@
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use sqlx::PgPool;
+use sqlx::types::chrono::NaiveDateTime;
+use crate::dtos::search::SearchResult;
+
+/// Stores the text search configuration in the database and re-indexes every message
+/// if it changed since the last start.
+pub async fn apply_search_config(pool: &PgPool, config: &str) -> Result<(), sqlx::Error> {
+    let changed = sqlx::query!(
+        r#"
+        UPDATE search_settings
+        SET config = $1::TEXT::REGCONFIG
+        WHERE config <> $1::TEXT::REGCONFIG
+        RETURNING id
+        "#,
+        config
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    if changed.is_some() {
+        tracing::info!(config, "Search configuration changed, re-indexing messages");
+
+        sqlx::query!("UPDATE messages SET search_vector = message_search_vector(content, file_path)")
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub struct SearchFilters {
+    pub query: String,
+    pub chat_id: Option<i32>,
+    pub sender_id: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub message_type: Option<String>,
+    pub cursor: Option<SearchCursor>,
+    pub limit: i64,
+}
+
+/// Position after the last returned result: results are ordered by rank, then by id.
+#[derive(Clone, Copy)]
+pub struct SearchCursor {
+    pub rank: f32,
+    pub message_id: i32,
+}
+
+impl SearchCursor {
+    pub fn encode(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(format!("{}:{}", self.rank, self.message_id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let decoded = BASE64_URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (rank, message_id) = decoded.split_once(':')?;
+
+        Some(SearchCursor {
+            rank: rank.parse().ok()?,
+            message_id: message_id.parse().ok()?,
+        })
+    }
+}
+
+/// Searches the messages of every chat the user is a member of and has not declined,
+/// leaving out messages from users they blocked.
+/// Returns one page of results and the cursor of the next page, if there is one.
+pub async fn search_messages(
+    pool: &PgPool,
+    user_id: i32,
+    filters: &SearchFilters,
+) -> Result<(Vec<SearchResult>, Option<SearchCursor>), sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            m.id,
+            m.chat_id,
+            m.user_id,
+            m.message_type,
+            m.created_at,
+            m.file_path,
+            ranked.rank AS "rank!",
+            ts_headline(
+                s.config,
+                replace(replace(replace(m.content, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+                q.query,
+                'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=20, MinWords=5'
+            ) AS "highlight!"
+        FROM search_settings s
+        CROSS JOIN LATERAL (SELECT websearch_to_tsquery(s.config, $2) AS query) q
+        JOIN messages m ON m.search_vector @@ q.query
+        JOIN chat_members cm ON cm.chat_id = m.chat_id AND cm.user_id = $1
+        CROSS JOIN LATERAL (SELECT ts_rank(m.search_vector, q.query) AS rank) ranked
+        WHERE m.deleted_at IS NULL
+          AND cm.request_status <> 'declined'
+          AND NOT EXISTS (SELECT 1 FROM user_blocks b WHERE b.blocker_id = $1 AND b.blocked_id = m.user_id)
+          AND NOT EXISTS (SELECT 1 FROM hidden_messages h WHERE h.message_id = m.id AND h.user_id = $1)
+          AND ($3::INTEGER IS NULL OR m.chat_id = $3)
+          AND ($4::INTEGER IS NULL OR m.user_id = $4)
+          AND ($5::TIMESTAMP IS NULL OR m.created_at >= $5)
+          AND ($6::TIMESTAMP IS NULL OR m.created_at < $6)
+          AND ($7::TEXT IS NULL OR m.message_type = $7)
+          AND ($8::REAL IS NULL OR (ranked.rank, m.id) < ($8, $9))
+        ORDER BY ranked.rank DESC, m.id DESC
+        LIMIT $10
+        "#,
+        user_id,
+        filters.query,
+        filters.chat_id,
+        filters.sender_id,
+        filters.from,
+        filters.to,
+        filters.message_type,
+        filters.cursor.map(|cursor| cursor.rank),
+        filters.cursor.map(|cursor| cursor.message_id),
+        filters.limit + 1
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut results: Vec<SearchResult> = rows.into_iter().map(|row| SearchResult {
+        message_id: row.id,
+        chat_id: row.chat_id,
+        user_id: row.user_id,
+        message_type: row.message_type,
+        created_at: row.created_at,
+        file_path: row.file_path,
+        highlight: row.highlight,
+        rank: row.rank,
+    }).collect();
+
+    // Запитуємо на один результат більше, щоб знати, чи є наступна сторінка
+    let next_cursor = if results.len() as i64 > filters.limit {
+        results.truncate(filters.limit as usize);
+        results.last().map(|last| SearchCursor { rank: last.rank, message_id: last.message_id })
+    } else {
+        None
+    };
+
+    Ok((results, next_cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_raw(raw: &str) -> Option<SearchCursor> {
+        SearchCursor::decode(&BASE64_URL_SAFE_NO_PAD.encode(raw))
+    }
+
+    #[test]
+    fn cursor_survives_a_round_trip() {
+        for cursor in [SearchCursor { rank: 0.0607927, message_id: 42 }, SearchCursor { rank: 1e-20, message_id: i32::MAX }] {
+            let decoded = SearchCursor::decode(&cursor.encode()).unwrap();
+
+            assert_eq!(decoded.rank, cursor.rank);
+            assert_eq!(decoded.message_id, cursor.message_id);
+        }
+    }
+
+    #[test]
+    fn cursor_with_malformed_base64_is_rejected() {
+        for cursor in ["", "not base64!", "MC41OjQy=", "MC41OjQy\n"] {
+            assert!(SearchCursor::decode(cursor).is_none(), "{:?}", cursor);
+        }
+    }
+
+    #[test]
+    fn cursor_with_malformed_rank_and_id_is_rejected() {
+        for raw in ["0.5", "0.5;42", ":42", "0.5:", "rank:42", "0.5:id", "0.5:4.2", "0.5:42:1", "0.5:99999999999"] {
+            assert!(decode_raw(raw).is_none(), "{:?}", raw);
+        }
+
+        let not_utf8 = BASE64_URL_SAFE_NO_PAD.encode([0xff, 0xfe, b':', b'1']);
+        assert!(SearchCursor::decode(&not_utf8).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use sqlx::PgPool;
+use crate::dtos::user::{UserProfileResponse, UserSummary};
+
+/// Escapes `LIKE` wildcards so user input only matches literally.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Users the viewer may find in the directory, best matches first: prefix matches on
+/// email or display name, then fuzzy (trigram) matches. Users who turned discoverability
+/// off are only found by people they already share a chat with. Emails are matched but
+/// never returned.
+pub async fn search_users(
+    pool: &PgPool,
+    viewer_id: i32,
+    query: &str,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<UserSummary>, sqlx::Error> {
+    let query = query.to_lowercase();
+    let prefix = format!("{}%", escape_like(&query));
+
+    let users = sqlx::query!(
+        r#"
+        SELECT
+            s.id AS "id!",
+            s.display_name AS "display_name!",
+            s.avatar_path,
+            s.status_text
+        FROM users u
+        JOIN user_summaries s ON s.id = u.id
+        WHERE u.id <> $1
+          AND (
+              u.discoverable
+              OR EXISTS (
+                  SELECT 1
+                  FROM chat_members me
+                  JOIN chat_members other ON other.chat_id = me.chat_id
+                  WHERE me.user_id = $1 AND other.user_id = u.id
+              )
+          )
+          AND (
+              lower(u.email) LIKE $3
+              OR lower(u.display_name) LIKE $3
+              OR $2 <% lower(u.email)
+              OR $2 <% lower(u.display_name)
+          )
+        ORDER BY
+            (lower(u.email) LIKE $3 OR COALESCE(lower(u.display_name) LIKE $3, FALSE)) DESC,
+            GREATEST(word_similarity($2, lower(u.email)), COALESCE(word_similarity($2, lower(u.display_name)), 0)) DESC,
+            u.id ASC
+        OFFSET $4
+        LIMIT $5
+        "#,
+        viewer_id,
+        query,
+        prefix,
+        offset,
+        limit
+    )
+        .fetch_all(pool)
+        .await?;
+
+    Ok(users.into_iter().map(|user| UserSummary::new(user.id, user.display_name, user.avatar_path, user.status_text)).collect())
+}
+
+/// Whether the viewer is allowed to find the user, by the same rules as `search_users`.
+pub async fn is_discoverable_by(pool: &PgPool, viewer_id: i32, user_id: i32) -> Result<bool, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"
+        SELECT u.id
+        FROM users u
+        WHERE u.id = $2
+          AND (
+              u.id = $1
+              OR u.discoverable
+              OR EXISTS (
+                  SELECT 1
+                  FROM chat_members me
+                  JOIN chat_members other ON other.chat_id = me.chat_id
+                  WHERE me.user_id = $1 AND other.user_id = u.id
+              )
+          )
+        "#,
+        viewer_id,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user.is_some())
+}
+
+pub async fn fetch_user_summary(pool: &PgPool, user_id: i32) -> Result<Option<UserSummary>, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"SELECT id AS "id!", display_name AS "display_name!", avatar_path, status_text FROM user_summaries WHERE id = $1"#,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user.map(|user| UserSummary::new(user.id, user.display_name, user.avatar_path, user.status_text)))
+}
+
+/// Profile of the user. The email is only included when `own` is set.
+pub async fn fetch_profile(pool: &PgPool, user_id: i32, own: bool) -> Result<Option<UserProfileResponse>, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            u.id,
+            u.email,
+            s.display_name AS "display_name!",
+            u.avatar_path,
+            u.bio,
+            s.status_text,
+            u.status_expires_at
+        FROM users u
+        JOIN user_summaries s ON s.id = u.id
+        WHERE u.id = $1
+        "#,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user.map(|user| {
+        let status_active = user.status_text.is_some();
+
+        UserProfileResponse {
+            id: user.id,
+            email: own.then_some(user.email),
+            display_name: user.display_name,
+            avatar_url: user.avatar_path.map(|path| format!("/api/files/{}", path)),
+            bio: user.bio,
+            status_text: user.status_text,
+            status_expires_at: if status_active { user.status_expires_at } else { None },
+        }
+    }))
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use crate::config::{LogFormat, LoggingConfig};
+use crate::metrics::QueryMetricsLayer;
+
+static REDACT_CONTENT: AtomicBool = AtomicBool::new(true);
+
+fn level_filter(config: &LoggingConfig) -> Result<EnvFilter, Box<dyn std::error::Error>> {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) => EnvFilter::try_new(directives)?,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+
+    Ok(filter)
+}
+
+/// Installs the global `tracing` subscriber: logs to stdout in the configured format and,
+/// when `logging.otlp_endpoint` is set, exports spans to an OpenTelemetry collector.
+/// Must be called from within the Tokio runtime.
+pub fn init(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
+    REDACT_CONTENT.store(config.redact_content, Ordering::Relaxed);
+
+    let output = match config.format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    let otlp = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", config.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)?;
+
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    // Запити sqlx логуються на рівні debug, тому метрики запитів фільтруються окремо від логів
+    tracing_subscriber::registry()
+        .with(output.with_filter(level_filter(config)?))
+        .with(otlp.with_filter(level_filter(config)?))
+        .with(QueryMetricsLayer.with_filter(Targets::new().with_target("sqlx::query", Level::DEBUG)))
+        .try_init()?;
+
+    Ok(())
+}
+
+/// Sends the spans that are still buffered to the collector.
+pub async fn shutdown() {
+    let _ = tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await;
+}
+
+/// User content in a log field. Shows only its size unless `logging.redact_content` is off.
+pub struct Redacted<'a>(pub &'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if REDACT_CONTENT.load(Ordering::Relaxed) {
+            write!(f, "[redacted, {} bytes]", self.0.len())
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Перемикач спільний для процесу, тож обидва режими перевіряються в одному тесті
+    #[test]
+    fn content_is_shown_only_when_redaction_is_off() {
+        REDACT_CONTENT.store(true, Ordering::Relaxed);
+        assert_eq!(Redacted("Привіт").to_string(), "[redacted, 12 bytes]");
+        assert_eq!(Redacted("").to_string(), "[redacted, 0 bytes]");
+
+        REDACT_CONTENT.store(false, Ordering::Relaxed);
+        assert_eq!(Redacted("Привіт").to_string(), "Привіт");
+
+        REDACT_CONTENT.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::Cursor;
+use image::imageops::FilterType;
+use image::ImageFormat;
+use rocket::data::{Data, ToByteUnit};
+use crate::config::UploadConfig;
+use crate::constants::common::AVATAR_SIZE;
+use crate::metrics::METRICS;
+
+pub enum AvatarError {
+    TooLarge,
+    UnsupportedFormat,
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for AvatarError {
+    fn from(error: std::io::Error) -> Self {
+        AvatarError::Io(error)
+    }
+}
+
+/// Reads an uploaded image in any format the decoder understands, crops it to a square
+/// and stores it as `<file_name>` in the upload directory in PNG.
+pub async fn save_avatar(data: Data<'_>, uploads: &UploadConfig, file_name: &str) -> Result<(), AvatarError> {
+    let upload = data.open(uploads.max_avatar_bytes.bytes()).into_bytes().await?;
+
+    if !upload.is_complete() {
+        return Err(AvatarError::TooLarge);
+    }
+
+    METRICS.upload_bytes.with_label_values(&["avatar"]).inc_by(upload.len() as u64);
+
+    // Декодування та масштабування зображення блокують потік, тому виконуються окремо
+    let avatar = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, image::ImageError> {
+        let avatar = image::load_from_memory(&upload.into_inner())?
+            .resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3);
+
+        let mut png = Vec::new();
+        avatar.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(png)
+    })
+        .await
+        .map_err(|e| AvatarError::Io(std::io::Error::other(e)))?
+        .map_err(|_| AvatarError::UnsupportedFormat)?;
+
+    tokio::fs::write(uploads.path_of(file_name), avatar).await?;
+
+    Ok(())
+}
@@ -1,2 +1,4 @@
 pub mod time;
-pub mod validators;
\ No newline at end of file
+pub mod validators;
+pub mod text;
+pub mod images;
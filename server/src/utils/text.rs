@@ -0,0 +1,10 @@
+/// Shortens text to at most `max_chars` characters, marking the cut with an ellipsis.
+pub fn snippet(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let mut shortened: String = text.chars().take(max_chars).collect();
+    shortened.push('…');
+    shortened
+}
@@ -1,5 +1,15 @@
 use std::time::{SystemTime, UNIX_EPOCH};
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
 
 pub fn get_current_timestamp() -> usize {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize
 }
+
+/// Parses a query parameter given either as a date (`2024-11-29`) or a date and time
+/// (`2024-11-29T18:30:00`). A bare date means its midnight.
+pub fn parse_date_param(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f"))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+}
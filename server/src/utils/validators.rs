@@ -1,6 +1,131 @@
 use regex::Regex;
+use crate::constants::common::MAX_REACTION_BYTES;
 
 pub fn is_email(email: &str) -> bool {
     let re = Regex::new(r"^[\w.-]+@([\w-]+\.)+[a-zA-Z]{2,4}$").unwrap();
     re.is_match(email)
 }
+
+/// Code point ranges of Extended_Pictographic characters, without the regional indicators and
+/// skin tone modifiers, which only count as part of a flag or after another emoji.
+const PICTOGRAPHIC: [(u32, u32); 30] = [
+    (0x00A9, 0x00A9), (0x00AE, 0x00AE), (0x203C, 0x203C), (0x2049, 0x2049),
+    (0x2122, 0x2122), (0x2139, 0x2139), (0x2194, 0x2199), (0x21A9, 0x21AA),
+    (0x231A, 0x231B), (0x2328, 0x2328), (0x2388, 0x2388), (0x23CF, 0x23CF),
+    (0x23E9, 0x23FA), (0x24C2, 0x24C2), (0x25AA, 0x25AB), (0x25B6, 0x25B6),
+    (0x25C0, 0x25C0), (0x25FB, 0x25FE), (0x2600, 0x27BF), (0x2934, 0x2935),
+    (0x2B05, 0x2B07), (0x2B1B, 0x2B1C), (0x2B50, 0x2B50), (0x2B55, 0x2B55),
+    (0x3030, 0x3030), (0x303D, 0x303D), (0x3297, 0x3297), (0x3299, 0x3299),
+    (0x1F000, 0x1F1E5), (0x1F200, 0x1FFFD),
+];
+
+const ZWJ: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+const TAG_END: char = '\u{E007F}';
+
+fn is_pictographic(c: char) -> bool {
+    let c = c as u32;
+    !is_skin_tone(c) && PICTOGRAPHIC.iter().any(|&(first, last)| (first..=last).contains(&c))
+}
+
+fn is_skin_tone(c: u32) -> bool {
+    (0x1F3FB..=0x1F3FF).contains(&c)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// One emoji of a ZWJ sequence: a pictograph with an optional presentation selector or skin tone,
+/// and optional tags, as in subdivision flags.
+fn is_emoji_element(chars: &[char]) -> bool {
+    let Some((&base, mut rest)) = chars.split_first() else {
+        return false;
+    };
+
+    if !is_pictographic(base) {
+        return false;
+    }
+
+    if let Some((&next, tail)) = rest.split_first() {
+        if next == VARIATION_SELECTOR || is_skin_tone(next as u32) {
+            rest = tail;
+        }
+    }
+
+    match rest.split_last() {
+        None => true,
+        Some((&TAG_END, tags)) => !tags.is_empty() && tags.iter().all(|&c| ('\u{E0020}'..='\u{E007E}').contains(&c)),
+        Some(_) => false,
+    }
+}
+
+/// Checks that a reaction is exactly one emoji: a pictograph or a ZWJ sequence of them,
+/// a keycap like 1️⃣, or a flag made of two regional indicators.
+pub fn is_reaction_emoji(emoji: &str) -> bool {
+    if emoji.is_empty() || emoji.len() > MAX_REACTION_BYTES {
+        return false;
+    }
+
+    let chars: Vec<char> = emoji.chars().collect();
+
+    match chars.as_slice() {
+        [key, KEYCAP] | [key, VARIATION_SELECTOR, KEYCAP] => key.is_ascii_digit() || *key == '#' || *key == '*',
+        [first, second] if is_regional_indicator(*first) => is_regional_indicator(*second),
+        _ => chars.split(|&c| c == ZWJ).all(is_emoji_element),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_emoji_are_reactions() {
+        let emoji = [
+            "👍", "❤", "❤️", "👍🏽", "👨‍👩‍👧", "🏳️‍🌈", "🇺🇦", "#️⃣", "*️⃣", "1️⃣", "0⃣",
+            "\u{1F3F4}\u{E0067}\u{E0062}\u{E0065}\u{E006E}\u{E0067}\u{E007F}",
+        ];
+
+        for emoji in emoji {
+            assert!(is_reaction_emoji(emoji), "{} should be a reaction", emoji);
+        }
+    }
+
+    #[test]
+    fn text_is_not_a_reaction() {
+        for text in ["", "a", "ok", "👍 ", " 👍", "👍\n", "<b>", "!", "#", "👍!", "😀a", "\u{200D}", "👍\u{200D}"] {
+            assert!(!is_reaction_emoji(text), "{:?} should not be a reaction", text);
+        }
+    }
+
+    #[test]
+    fn digits_are_not_reactions() {
+        for text in ["1", "123", "1\u{FE0F}", "12\u{20E3}", "١"] {
+            assert!(!is_reaction_emoji(text), "{:?} should not be a reaction", text);
+        }
+    }
+
+    #[test]
+    fn words_in_other_scripts_are_not_reactions() {
+        for text in ["привіт", "你好", "é", "ß", "مرحبا", "😀привіт"] {
+            assert!(!is_reaction_emoji(text), "{:?} should not be a reaction", text);
+        }
+    }
+
+    #[test]
+    fn several_emoji_are_not_one_reaction() {
+        for text in ["👍👍", "👍❤️", "🇺🇦🇺", "🇺🇦🇵🇱", "🏽", "🏽👍"] {
+            assert!(!is_reaction_emoji(text), "{:?} should not be a reaction", text);
+        }
+    }
+
+    #[test]
+    fn long_sequences_are_not_reactions() {
+        let sequence = ["👍"; 8].join("\u{200D}");
+
+        assert!(sequence.len() > MAX_REACTION_BYTES);
+        assert!(!is_reaction_emoji(&sequence));
+    }
+}
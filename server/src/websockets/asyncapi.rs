@@ -0,0 +1,106 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Value};
+use crate::config::WebSocketConfig;
+use crate::dtos::chat::ReplyPreview;
+use crate::dtos::user::UserSummary;
+use crate::websockets::events::{ClientEvent, ServerEvent};
+use crate::websockets::server::NewMessageRequest;
+
+const ASYNCAPI_VERSION: &str = "2.6.0";
+
+/// Describes the WebSocket protocol as an AsyncAPI document. The payload schemas are generated
+/// from the types the server reads and writes, so they change together with the code.
+pub fn asyncapi_document(websocket: &WebSocketConfig) -> Value {
+    let settings = SchemaSettings::draft07().with(|settings| {
+        settings.definitions_path = "#/components/schemas/".to_string();
+    });
+    let mut generator = SchemaGenerator::new(settings);
+
+    let client_event = generator.subschema_for::<ClientEvent>();
+    let server_event = generator.subschema_for::<ServerEvent>();
+    let new_message = generator.subschema_for::<NewMessageRequest>();
+    let reply_preview = generator.subschema_for::<ReplyPreview>();
+    let user_summary = generator.subschema_for::<UserSummary>();
+    let schemas = generator.take_definitions();
+
+    json!({
+        "asyncapi": ASYNCAPI_VERSION,
+        "info": {
+            "title": "ChatApp WebSocket API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Real-time messages and events. Every frame is a JSON text message.",
+        },
+        "servers": {
+            "websocket": {
+                "url": websocket.bind_address.to_string(),
+                "protocol": "ws",
+                "description": "Browsers can't send an Authorization header, so the token is passed as `?token=<JWT>`. Connections with an invalid token are closed right after the handshake.",
+            },
+        },
+        "channels": {
+            "/": {
+                "bindings": {
+                    "ws": {
+                        "query": {
+                            "type": "object",
+                            "properties": { "token": { "type": "string", "description": "The token returned by `POST /api/users/login`" } },
+                            "required": ["token"],
+                        },
+                    },
+                },
+                "publish": {
+                    "summary": "Frames the client sends",
+                    "message": {
+                        "oneOf": [
+                            { "$ref": "#/components/messages/NewMessage" },
+                            { "$ref": "#/components/messages/ClientEvent" },
+                        ],
+                    },
+                },
+                "subscribe": {
+                    "summary": "Frames the server pushes",
+                    "message": {
+                        "oneOf": [
+                            { "$ref": "#/components/messages/ChatMessage" },
+                            { "$ref": "#/components/messages/ServerEvent" },
+                        ],
+                    },
+                },
+            },
+        },
+        "components": {
+            "messages": {
+                "NewMessage": {
+                    "summary": "Sends a chat message. `chat_id` and `user_id` may be numbers or numeric strings.",
+                    "payload": new_message,
+                },
+                "ClientEvent": {
+                    "summary": "Typing, presence, read receipts, thread subscriptions and reactions",
+                    "payload": client_event,
+                },
+                "ChatMessage": {
+                    "summary": "A new message in one of the user's chats, as sent by its author",
+                    "payload": {
+                        "allOf": [
+                            new_message,
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "id": { "type": "integer" },
+                                    "reply_to": { "anyOf": [reply_preview, { "type": "null" }] },
+                                    "sender": { "anyOf": [user_summary, { "type": "null" }] },
+                                },
+                                "required": ["id"],
+                            },
+                        ],
+                    },
+                },
+                "ServerEvent": {
+                    "summary": "An event tagged by `type`",
+                    "payload": server_event,
+                },
+            },
+            "schemas": schemas,
+        },
+    })
+}
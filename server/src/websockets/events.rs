@@ -0,0 +1,75 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::dtos::chat::{ChatRequestStatus, ChatRole, ChatSettings, DeleteScope};
+use crate::dtos::invite::InviteUseStatus;
+use crate::dtos::user::PresenceStatus;
+
+/// Events a client can send besides a plain chat message.
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientEvent {
+    TypingStarted { chat_id: i32 },
+    TypingStopped { chat_id: i32 },
+    SetPresence { status: PresenceStatus },
+    MarkRead { chat_id: i32, message_id: i32 },
+    SubscribeThread { chat_id: i32, message_id: i32 },
+    UnsubscribeThread { message_id: i32 },
+    AddReaction { chat_id: i32, message_id: i32, emoji: String },
+    RemoveReaction { chat_id: i32, message_id: i32, emoji: String },
+}
+
+/// Events pushed by the server on top of plain chat messages.
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    TypingStarted { chat_id: i32, user_id: i32 },
+    TypingStopped { chat_id: i32, user_id: i32 },
+    PresenceChanged {
+        user_id: i32,
+        status: PresenceStatus,
+        last_seen: Option<NaiveDateTime>,
+    },
+    MessagesDelivered { chat_id: i32, user_id: i32, message_id: i32 },
+    MessagesRead { chat_id: i32, user_id: i32, message_id: i32 },
+    MessageEdited {
+        chat_id: i32,
+        message_id: i32,
+        content: String,
+        edited_at: NaiveDateTime,
+    },
+    MessageDeleted { chat_id: i32, message_id: i32, scope: DeleteScope },
+    ThreadReply {
+        chat_id: i32,
+        thread_root_id: i32,
+        message: serde_json::Value,
+    },
+    ThreadUpdated {
+        chat_id: i32,
+        message_id: i32,
+        reply_count: i32,
+        last_reply_at: Option<NaiveDateTime>,
+    },
+    ReactionAdded { chat_id: i32, message_id: i32, user_id: i32, emoji: String },
+    ReactionRemoved { chat_id: i32, message_id: i32, user_id: i32, emoji: String },
+    ChatSettingsChanged { chat_id: i32, settings: ChatSettings },
+    ChatCreated { chat_id: i32, created_by: i32 },
+    ChatUpdated { chat_id: i32, title: Option<String>, avatar_url: Option<String> },
+    MemberAdded { chat_id: i32, user_id: i32, role: ChatRole },
+    MemberRemoved { chat_id: i32, user_id: i32 },
+    MemberRoleChanged { chat_id: i32, user_id: i32, role: ChatRole },
+    MessagePinned { chat_id: i32, message_id: i32, pinned_by: i32 },
+    MessageUnpinned { chat_id: i32, message_id: i32 },
+    JoinRequested { chat_id: i32, invite_id: i32, user_id: i32 },
+    JoinRequestReviewed { chat_id: i32, status: InviteUseStatus },
+    ChatRequestAnswered { chat_id: i32, user_id: i32, status: ChatRequestStatus },
+    PinnedChatsReordered { chat_ids: Vec<i32> },
+    Error { message: String },
+}
+
+impl ServerEvent {
+    pub fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).unwrap_or_default())
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use crate::config::{FanOutBackend, FanOutConfig};
+use crate::constants::common::{FAN_OUT_RECONNECT_SECONDS, PRESENCE_SNAPSHOT_SECONDS};
+use crate::dtos::user::PresenceStatus;
+use crate::websockets::hub::Hub;
+
+pub mod pg_notify;
+pub mod redis_pubsub;
+
+use self::pg_notify::PgNotifyFanOut;
+use self::redis_pubsub::RedisFanOut;
+
+pub type FanOutError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A broadcast that every instance delivers to its own sockets, or a change to chat members
+/// or presence that every instance applies to its hub.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "to", rename_all = "snake_case")]
+pub enum Delivery {
+    Users { user_ids: Vec<i32>, payload: String },
+    Thread { thread_root_id: i32, user_ids: Vec<i32>, payload: String },
+    ChatMembers { chat_id: i32 },
+    MemberChats { user_id: i32 },
+    Presence { user_id: i32, status: PresenceStatus },
+    PresenceSnapshot { statuses: Vec<(i32, PresenceStatus)> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub origin: u64,    // Instance that published the broadcast
+    pub delivery: Delivery,
+}
+
+/// Carries broadcasts between server instances.
+#[rocket::async_trait]
+pub trait FanOut: Send + Sync {
+    async fn publish(&self, envelope: &Envelope) -> Result<(), FanOutError>;
+
+    /// Passes every envelope published by any instance, this one included, to `on_envelope`.
+    /// Returns only when the subscription fails; envelopes published meanwhile are lost.
+    async fn listen(&self, on_envelope: &(dyn Fn(Envelope) + Send + Sync)) -> Result<(), FanOutError>;
+}
+
+/// Builds the configured backend, or `None` for a single instance.
+pub async fn connect(config: &FanOutConfig, pool: &PgPool) -> Result<Option<Arc<dyn FanOut>>, FanOutError> {
+    let fan_out: Arc<dyn FanOut> = match config.backend {
+        FanOutBackend::Local => return Ok(None),
+        FanOutBackend::Postgres => Arc::new(PgNotifyFanOut::new(pool.clone(), &config.channel)),
+        FanOutBackend::Redis => {
+            let url = config.redis_url.as_ref().ok_or("fan_out.redis_url is not set")?;
+            Arc::new(RedisFanOut::connect(url.expose(), &config.channel).await?)
+        }
+    };
+
+    Ok(Some(fan_out))
+}
+
+/// Random per process, so instances in identical containers still tell themselves apart.
+fn instance_id() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Links the hub to the other instances: what it broadcasts is published, and what the others
+/// publish is delivered to the local sockets. The hub delivers its own broadcasts directly.
+pub fn start(fan_out: Arc<dyn FanOut>, hub: Arc<Hub>) {
+    let origin = instance_id();
+    let (outbound, mut to_publish) = mpsc::unbounded_channel();
+    hub.set_fan_out(outbound);
+
+    // Знімок присутності заразом показує іншим інстансам, що цей ще працює
+    let snapshots = hub.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PRESENCE_SNAPSHOT_SECONDS));
+
+        loop {
+            interval.tick().await;
+            snapshots.publish_presence_snapshot();
+        }
+    });
+
+    let publisher = fan_out.clone();
+    tokio::spawn(async move {
+        // Одна задача публікує все по черзі, тож порядок трансляцій зберігається
+        while let Some(delivery) = to_publish.recv().await {
+            if let Err(e) = publisher.publish(&Envelope { origin, delivery }).await {
+                tracing::error!(error = %e, "Failed to publish broadcast to other instances");
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let on_envelope = |envelope: Envelope| {
+            if envelope.origin != origin {
+                hub.deliver(envelope.origin, &envelope.delivery);
+            }
+        };
+
+        loop {
+            // Поки підписки не було, зміни учасників з інших інстансів могли загубитися
+            hub.forget_all_chats();
+
+            if let Err(e) = fan_out.listen(&on_envelope).await {
+                tracing::warn!(error = %e, "Lost the fan-out subscription, reconnecting");
+            }
+
+            tokio::time::sleep(Duration::from_secs(FAN_OUT_RECONNECT_SECONDS)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(10);
+
+    /// Publishes the deliveries through `fan_out` and returns what its own listener received,
+    /// in the order it arrived. Pings first until the listener is subscribed.
+    pub async fn round_trip(fan_out: Arc<dyn FanOut>, deliveries: Vec<Delivery>) -> Vec<Delivery> {
+        let (received, mut envelopes) = mpsc::unbounded_channel();
+        let listener = fan_out.clone();
+        let listening = tokio::spawn(async move {
+            let on_envelope = move |envelope| {
+                let _ = received.send(envelope);
+            };
+            let _ = listener.listen(&on_envelope).await;
+        });
+
+        let ping = Envelope { origin: 0, delivery: Delivery::ChatMembers { chat_id: 0 } };
+        tokio::time::timeout(WAIT, async {
+            loop {
+                fan_out.publish(&ping).await.expect("Failed to publish ping");
+
+                if let Ok(Some(_)) = tokio::time::timeout(Duration::from_millis(200), envelopes.recv()).await {
+                    break;
+                }
+            }
+        })
+            .await
+            .expect("Listener did not subscribe");
+
+        for delivery in deliveries.iter().cloned() {
+            fan_out.publish(&Envelope { origin: 1, delivery }).await.expect("Failed to publish");
+        }
+
+        let mut delivered = Vec::new();
+        tokio::time::timeout(WAIT, async {
+            while delivered.len() < deliveries.len() {
+                match envelopes.recv().await {
+                    Some(envelope) if envelope.origin == 1 => delivered.push(envelope.delivery),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        })
+            .await
+            .expect("Not every broadcast arrived");
+
+        listening.abort();
+        delivered
+    }
+
+    pub fn broadcasts(large_payload_bytes: usize) -> Vec<Delivery> {
+        vec![
+            Delivery::Users { user_ids: vec![1, 2], payload: "{\"id\":1}".to_string() },
+            Delivery::Thread { thread_root_id: 5, user_ids: vec![3], payload: "x".repeat(large_payload_bytes) },
+            Delivery::Presence { user_id: 4, status: PresenceStatus::Away },
+        ]
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use crate::constants::common::{FAN_OUT_PAYLOAD_RETENTION_SECONDS, MAX_NOTIFY_PAYLOAD_BYTES};
+use crate::websockets::fan_out::{Envelope, FanOut, FanOutError};
+
+/// Either the envelope itself or, when it is too large for NOTIFY, the row it was stored in.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Notification {
+    Stored { stored: i64 },
+    Inline(Envelope),
+}
+
+/// Fan-out over Postgres `LISTEN`/`NOTIFY` on the main database, so no other service is needed.
+pub struct PgNotifyFanOut {
+    pool: PgPool,
+    channel: String,
+}
+
+impl PgNotifyFanOut {
+    pub fn new(pool: PgPool, channel: &str) -> Self {
+        PgNotifyFanOut { pool, channel: channel.to_string() }
+    }
+
+    async fn notify(&self, payload: &str) -> Result<(), sqlx::Error> {
+        // pg_notify повертає void, який макрос query! не вміє декодувати
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(&self.channel)
+            .bind(payload)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn store(&self, envelope: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM fan_out_payloads WHERE created_at < NOW() - make_interval(secs => $1)",
+            FAN_OUT_PAYLOAD_RETENTION_SECONDS as f64
+        )
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query_scalar!("INSERT INTO fan_out_payloads (envelope) VALUES ($1) RETURNING id", envelope)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn read(&self, notification: Notification) -> Result<Option<Envelope>, FanOutError> {
+        match notification {
+            Notification::Inline(envelope) => Ok(Some(envelope)),
+            Notification::Stored { stored } => {
+                let envelope = sqlx::query_scalar!("SELECT envelope FROM fan_out_payloads WHERE id = $1", stored)
+                    .fetch_optional(&self.pool)
+                    .await?;
+
+                match envelope {
+                    Some(envelope) => Ok(Some(serde_json::from_str(&envelope)?)),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl FanOut for PgNotifyFanOut {
+    async fn publish(&self, envelope: &Envelope) -> Result<(), FanOutError> {
+        let envelope = serde_json::to_string(envelope)?;
+
+        if envelope.len() <= MAX_NOTIFY_PAYLOAD_BYTES {
+            self.notify(&envelope).await?;
+        } else {
+            let stored = self.store(&envelope).await?;
+            self.notify(&serde_json::to_string(&Notification::Stored { stored })?).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn listen(&self, on_envelope: &(dyn Fn(Envelope) + Send + Sync)) -> Result<(), FanOutError> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(&self.channel).await?;
+
+        loop {
+            let notification = listener.recv().await?;
+
+            let envelope = match serde_json::from_str::<Notification>(notification.payload()) {
+                Ok(notification) => self.read(notification).await,
+                Err(e) => Err(e.into()),
+            };
+
+            match envelope {
+                Ok(Some(envelope)) => on_envelope(envelope),
+                Ok(None) => tracing::warn!("Stored broadcast expired before it was read"),
+                Err(e) => tracing::warn!(error = %e, "Failed to read broadcast from another instance"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::websockets::fan_out::tests::{broadcasts, round_trip};
+
+    #[tokio::test]
+    #[ignore = "needs a migrated Postgres database at DATABASE_URL"]
+    async fn delivers_broadcasts_over_and_under_the_notify_limit() {
+        let pool = PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        let fan_out = Arc::new(PgNotifyFanOut::new(pool.clone(), "fan_out_test"));
+        let deliveries = broadcasts(MAX_NOTIFY_PAYLOAD_BYTES * 2);
+
+        assert_eq!(round_trip(fan_out, deliveries.clone()).await, deliveries);
+
+        // Завелика трансляція пройшла через таблицю
+        let stored = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM fan_out_payloads"#)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(stored > 0);
+    }
+}
@@ -0,0 +1,70 @@
+use futures_util::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use crate::websockets::fan_out::{Envelope, FanOut, FanOutError};
+
+/// Fan-out over Redis pub/sub, for deployments that already run Redis.
+pub struct RedisFanOut {
+    client: redis::Client,
+    publisher: ConnectionManager,
+    channel: String,
+}
+
+impl RedisFanOut {
+    pub async fn connect(url: &str, channel: &str) -> Result<Self, FanOutError> {
+        let client = redis::Client::open(url)?;
+        let publisher = ConnectionManager::new(client.clone()).await?;
+
+        Ok(RedisFanOut { client, publisher, channel: channel.to_string() })
+    }
+}
+
+#[rocket::async_trait]
+impl FanOut for RedisFanOut {
+    async fn publish(&self, envelope: &Envelope) -> Result<(), FanOutError> {
+        let envelope = serde_json::to_string(envelope)?;
+
+        // ConnectionManager клонується дешево й сам перепідключається
+        let mut publisher = self.publisher.clone();
+        publisher.publish::<_, _, ()>(&self.channel, envelope).await?;
+
+        Ok(())
+    }
+
+    async fn listen(&self, on_envelope: &(dyn Fn(Envelope) + Send + Sync)) -> Result<(), FanOutError> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(&self.channel).await?;
+
+        let mut messages = pubsub.on_message();
+
+        while let Some(message) = messages.next().await {
+            let envelope = message
+                .get_payload::<String>()
+                .map_err(FanOutError::from)
+                .and_then(|payload| serde_json::from_str::<Envelope>(&payload).map_err(FanOutError::from));
+
+            match envelope {
+                Ok(envelope) => on_envelope(envelope),
+                Err(e) => tracing::warn!(error = %e, "Failed to read broadcast from another instance"),
+            }
+        }
+
+        Err("Redis closed the subscription".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::websockets::fan_out::tests::{broadcasts, round_trip};
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    async fn delivers_broadcasts() {
+        let fan_out = RedisFanOut::connect(&std::env::var("REDIS_URL").unwrap(), "fan_out_test").await.unwrap();
+        let deliveries = broadcasts(64 * 1024);
+
+        assert_eq!(round_trip(Arc::new(fan_out), deliveries.clone()).await, deliveries);
+    }
+}
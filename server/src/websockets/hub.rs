@@ -0,0 +1,589 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::constants::common::{AWAY_AFTER_SECONDS, CHAT_AUDIENCE_TTL_SECONDS, REMOTE_PRESENCE_TTL_SECONDS, TYPING_TIMEOUT_SECONDS};
+use crate::dtos::user::PresenceStatus;
+use crate::metrics::METRICS;
+use crate::websockets::fan_out::Delivery;
+
+struct Client {
+    id: usize,
+    sender: mpsc::UnboundedSender<Message>,
+    away: bool,
+    last_activity: Instant,
+    threads: HashSet<i32>,
+}
+
+impl Client {
+    /// Queues the frame for the socket's writer, which takes it off the queue depth once written.
+    fn deliver(&self, message: &Message) -> bool {
+        let delivered = self.sender.send(message.clone()).is_ok();
+
+        if delivered {
+            METRICS.outbound_queue_depth.inc();
+        }
+
+        delivered
+    }
+}
+
+struct Typing {
+    expires_at: Instant,
+    recipients: Vec<i32>,
+}
+
+/// Who receives what is posted in a chat: the members who have not declined it, and which of
+/// them blocked which other member.
+#[derive(Default)]
+pub struct ChatAudience {
+    pub members: Vec<i32>,
+    pub blocks: HashSet<(i32, i32)>,  // (хто заблокував, кого заблокував)
+}
+
+struct CachedAudience {
+    audience: ChatAudience,
+    loaded_at: Instant,
+}
+
+/// Presence of the users connected to another instance, as that instance last published it.
+struct RemoteInstance {
+    statuses: HashMap<i32, PresenceStatus>,
+    seen_at: Instant,
+}
+
+/// A typing indicator that timed out without an explicit `typing_stopped`.
+pub struct ExpiredTyping {
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub recipients: Vec<i32>,
+}
+
+/// In-memory registry of open sockets, shared by the WebSocket server and the REST routes.
+/// Presence and typing state are derived from it and are never persisted. Broadcasts to users
+/// and threads are also published to the other instances, and so is the presence of the users
+/// connected here, so presence and delivery receipts account for sockets on every instance.
+/// A typing indicator lives on the instance of the socket it was started from and expires there.
+///
+/// Sockets are indexed by user in a sharded map, so a broadcast touches only the recipients'
+/// entries and broadcasts to different users rarely wait for each other.
+///
+/// The audience of recently used chats is kept as well, so sending a message does not have to
+/// read the chat's members from the database. Whoever changes the members or blocks calls
+/// `forget_chat` or `forget_member_chats`, which the other instances are told about too.
+#[derive(Default)]
+pub struct Hub {
+    next_id: AtomicUsize,
+    users: DashMap<i32, Vec<Client>>,   // Every open socket of the user, one per device
+    connections: DashMap<usize, i32>,   // Connection id to user id
+    typing: Mutex<HashMap<(i32, i32), Typing>>,
+    statuses: Mutex<HashMap<i32, PresenceStatus>>,   // Last announced presence, all instances together
+    published: Mutex<HashMap<i32, PresenceStatus>>,  // Last published presence of the sockets here
+    remote: Mutex<HashMap<u64, RemoteInstance>>,
+    chats: DashMap<i32, CachedAudience>,
+    chats_version: AtomicU64,   // Зростає з кожною зміною учасників, щоб не зберегти застарілий склад
+    fan_out: OnceLock<mpsc::UnboundedSender<Delivery>>,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes broadcasts through `sender` from now on.
+    pub fn set_fan_out(&self, sender: mpsc::UnboundedSender<Delivery>) {
+        let _ = self.fan_out.set(sender);
+    }
+
+    fn publish(&self, delivery: Delivery) {
+        if let Some(fan_out) = self.fan_out.get() {
+            let _ = fan_out.send(delivery);
+        }
+    }
+
+    /// Delivers a broadcast published by another instance to the local sockets.
+    pub fn deliver(&self, origin: u64, delivery: &Delivery) {
+        match delivery {
+            Delivery::Users { user_ids, payload } => {
+                self.deliver_to_users(user_ids, &Message::Text(payload.clone()));
+            }
+            Delivery::Thread { thread_root_id, user_ids, payload } => {
+                self.deliver_to_thread(*thread_root_id, user_ids, &Message::Text(payload.clone()));
+            }
+            Delivery::ChatMembers { chat_id } => self.drop_chat(*chat_id),
+            Delivery::MemberChats { user_id } => self.drop_member_chats(*user_id),
+            Delivery::Presence { user_id, status } => {
+                self.update_remote(origin, |statuses| {
+                    set_status(statuses, *user_id, *status);
+                    vec![*user_id]
+                });
+            }
+            Delivery::PresenceSnapshot { statuses } => {
+                self.update_remote(origin, |known| {
+                    let mut changed: Vec<i32> = known.keys().copied().collect();
+                    *known = statuses.iter().copied().collect();
+                    changed.extend(known.keys());
+                    changed
+                });
+            }
+        }
+    }
+
+    pub fn register(&self, user_id: i32, sender: mpsc::UnboundedSender<Message>) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.users.entry(user_id).or_default().push(Client {
+            id,
+            sender,
+            away: false,
+            last_activity: Instant::now(),
+            threads: HashSet::new(),
+        });
+        self.connections.insert(id, user_id);
+        METRICS.websocket_connections.inc();
+
+        id
+    }
+
+    pub fn unregister(&self, connection_id: usize) {
+        let Some((_, user_id)) = self.connections.remove(&connection_id) else {
+            return;
+        };
+
+        if let Some(mut clients) = self.users.get_mut(&user_id) {
+            clients.retain(|client| client.id != connection_id);
+        }
+        self.users.remove_if(&user_id, |_, clients| clients.is_empty());
+        METRICS.websocket_connections.dec();
+    }
+
+    /// Runs `f` on the socket while holding only its user's shard.
+    fn with_client(&self, connection_id: usize, f: impl FnOnce(&mut Client)) {
+        // Копіюємо id користувача, щоб не тримати дві мапи заблокованими одночасно
+        let Some(user_id) = self.connections.get(&connection_id).map(|user_id| *user_id) else {
+            return;
+        };
+
+        if let Some(mut clients) = self.users.get_mut(&user_id) {
+            if let Some(client) = clients.iter_mut().find(|client| client.id == connection_id) {
+                f(client);
+            }
+        }
+    }
+
+    /// Marks the socket as active, e.g. after it sent a frame.
+    pub fn touch(&self, connection_id: usize) {
+        self.with_client(connection_id, |client| client.last_activity = Instant::now());
+    }
+
+    pub fn set_away(&self, connection_id: usize, away: bool) {
+        self.with_client(connection_id, |client| {
+            client.away = away;
+            client.last_activity = Instant::now();
+        });
+    }
+
+    pub fn subscribe_thread(&self, connection_id: usize, thread_root_id: i32) {
+        self.with_client(connection_id, |client| {
+            client.threads.insert(thread_root_id);
+        });
+    }
+
+    pub fn unsubscribe_thread(&self, connection_id: usize, thread_root_id: i32) {
+        self.with_client(connection_id, |client| {
+            client.threads.remove(&thread_root_id);
+        });
+    }
+
+    pub fn send_to_connection(&self, connection_id: usize, message: &Message) {
+        self.with_client(connection_id, |client| {
+            client.deliver(message);
+        });
+    }
+
+    /// Delivers to the matching sockets of each listed user once, even if the user is listed twice.
+    fn deliver_to_matching(&self, user_ids: &[i32], message: &Message, matches: impl Fn(&Client) -> bool) {
+        let mut seen = HashSet::with_capacity(user_ids.len());
+        let mut recipients = 0;
+
+        for user_id in user_ids {
+            if !seen.insert(*user_id) {
+                continue;
+            }
+
+            if let Some(clients) = self.users.get(user_id) {
+                recipients += clients
+                    .iter()
+                    .filter(|client| matches(client))
+                    .filter(|client| client.deliver(message))
+                    .count();
+            }
+        }
+
+        METRICS.broadcast_recipients.observe(recipients as f64);
+    }
+
+    /// Sends the message to those sockets of the listed users that have the thread open.
+    pub fn send_to_thread(&self, thread_root_id: i32, user_ids: &[i32], message: &Message) {
+        self.deliver_to_thread(thread_root_id, user_ids, message);
+
+        if let Message::Text(payload) = message {
+            self.publish(Delivery::Thread { thread_root_id, user_ids: user_ids.to_vec(), payload: payload.clone() });
+        }
+    }
+
+    fn deliver_to_thread(&self, thread_root_id: i32, user_ids: &[i32], message: &Message) {
+        self.deliver_to_matching(user_ids, message, |client| client.threads.contains(&thread_root_id));
+    }
+
+    /// Sends the message to every open socket of this instance.
+    pub fn send_to_all(&self, message: &Message) {
+        for clients in self.users.iter() {
+            for client in clients.iter() {
+                client.deliver(message);
+            }
+        }
+    }
+
+    /// Sends the message to every socket of every listed user.
+    pub fn send_to_users(&self, user_ids: &[i32], message: &Message) {
+        self.deliver_to_users(user_ids, message);
+
+        if let Message::Text(payload) = message {
+            self.publish(Delivery::Users { user_ids: user_ids.to_vec(), payload: payload.clone() });
+        }
+    }
+
+    fn deliver_to_users(&self, user_ids: &[i32], message: &Message) {
+        self.deliver_to_matching(user_ids, message, |_| true);
+    }
+
+    /// Members who receive what the sender posts in the chat, or `None` if the chat's audience
+    /// is not known, in which case it has to be loaded and passed to `remember_chat`.
+    pub fn chat_recipients(&self, chat_id: i32, sender_id: i32) -> Option<Vec<i32>> {
+        let ttl = Duration::from_secs(CHAT_AUDIENCE_TTL_SECONDS);
+        let cached = self.chats.get(&chat_id).filter(|cached| cached.loaded_at.elapsed() < ttl)?;
+
+        Some(cached.audience.members
+            .iter()
+            .copied()
+            .filter(|member| !cached.audience.blocks.contains(&(*member, sender_id)))
+            .collect())
+    }
+
+    /// Changes with every forgotten chat. Read it before loading an audience.
+    pub fn chats_version(&self) -> u64 {
+        self.chats_version.load(Ordering::Acquire)
+    }
+
+    /// Keeps the audience loaded at `version`, unless the chat changed while it was loading.
+    pub fn remember_chat(&self, chat_id: i32, audience: ChatAudience, version: u64) {
+        self.chats.insert(chat_id, CachedAudience { audience, loaded_at: Instant::now() });
+
+        // Зміна могла статися між перевіркою та вставкою, тож перевіряємо вже після неї
+        if self.chats_version() != version {
+            self.chats.remove(&chat_id);
+        }
+    }
+
+    /// Drops the chat's audience here and on the other instances, e.g. after a member joined,
+    /// left or answered a chat request.
+    pub fn forget_chat(&self, chat_id: i32) {
+        self.drop_chat(chat_id);
+        self.publish(Delivery::ChatMembers { chat_id });
+    }
+
+    /// Drops the audience of every chat of the user here and on the other instances,
+    /// e.g. after they blocked or unblocked someone.
+    pub fn forget_member_chats(&self, user_id: i32) {
+        self.drop_member_chats(user_id);
+        self.publish(Delivery::MemberChats { user_id });
+    }
+
+    /// Drops every known audience, e.g. after invalidations from other instances may have been lost.
+    pub fn forget_all_chats(&self) {
+        self.chats_version.fetch_add(1, Ordering::AcqRel);
+        self.chats.clear();
+    }
+
+    fn drop_chat(&self, chat_id: i32) {
+        self.chats_version.fetch_add(1, Ordering::AcqRel);
+        self.chats.remove(&chat_id);
+    }
+
+    fn drop_member_chats(&self, user_id: i32) {
+        self.chats_version.fetch_add(1, Ordering::AcqRel);
+        self.chats.retain(|_, cached| !cached.audience.members.contains(&user_id));
+    }
+
+    /// Whether the user has a socket on this or another instance.
+    pub fn is_connected(&self, user_id: i32) -> bool {
+        self.has_local_sockets(user_id) || self.remote_presence(user_id) != PresenceStatus::Offline
+    }
+
+    pub fn has_local_sockets(&self, user_id: i32) -> bool {
+        self.users.get(&user_id).is_some_and(|clients| !clients.is_empty())
+    }
+
+    /// Users with a socket on this instance.
+    pub fn connected_user_ids(&self) -> Vec<i32> {
+        self.users
+            .iter()
+            .filter(|entry| !entry.value().is_empty())
+            .map(|entry| *entry.key())
+            .collect()
+    }
+
+    /// Online if any of the user's sockets on any instance is active, away if all of them are
+    /// idle or flagged away by the client, offline without sockets.
+    pub fn presence(&self, user_id: i32) -> PresenceStatus {
+        most_present(self.local_presence(user_id), self.remote_presence(user_id))
+    }
+
+    fn local_presence(&self, user_id: i32) -> PresenceStatus {
+        let away_after = Duration::from_secs(AWAY_AFTER_SECONDS);
+
+        // Запис без сокетів може на мить лишитися, поки unregister його не прибрав
+        let Some(clients) = self.users.get(&user_id).filter(|clients| !clients.is_empty()) else {
+            return PresenceStatus::Offline;
+        };
+
+        if clients.iter().any(|client| !client.away && client.last_activity.elapsed() < away_after) {
+            PresenceStatus::Online
+        } else {
+            PresenceStatus::Away
+        }
+    }
+
+    fn remote_presence(&self, user_id: i32) -> PresenceStatus {
+        let ttl = Duration::from_secs(REMOTE_PRESENCE_TTL_SECONDS);
+
+        self.remote
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|instance| instance.seen_at.elapsed() < ttl)
+            .filter_map(|instance| instance.statuses.get(&user_id).copied())
+            .fold(PresenceStatus::Offline, most_present)
+    }
+
+    /// Recomputes the user's presence and returns it if it differs from the last announced one.
+    /// A change of the sockets here is published to the other instances.
+    pub fn refresh_presence(&self, user_id: i32) -> Option<PresenceStatus> {
+        let local = self.local_presence(user_id);
+
+        if set_status(&mut self.published.lock().unwrap(), user_id, local) {
+            self.publish(Delivery::Presence { user_id, status: local });
+        }
+
+        let status = self.presence(user_id);
+        set_status(&mut self.statuses.lock().unwrap(), user_id, status).then_some(status)
+    }
+
+    /// Drops the last announced presence so the next refresh reports it again.
+    pub fn forget_presence(&self, user_id: i32) {
+        self.statuses.lock().unwrap().remove(&user_id);
+    }
+
+    /// Publishes the presence of every user connected here. The other instances replace what they
+    /// know about this one with it, and forget this instance if it stops publishing.
+    pub fn publish_presence_snapshot(&self) {
+        let statuses = self.published.lock().unwrap().iter().map(|(user_id, status)| (*user_id, *status)).collect();
+        self.publish(Delivery::PresenceSnapshot { statuses });
+    }
+
+    /// Forgets instances that stopped publishing, e.g. because they crashed, and returns
+    /// the users that were connected to them.
+    pub fn expire_remote_presence(&self) -> Vec<i32> {
+        let ttl = Duration::from_secs(REMOTE_PRESENCE_TTL_SECONDS);
+        let mut remote = self.remote.lock().unwrap();
+        let expired: Vec<u64> = remote
+            .iter()
+            .filter(|(_, instance)| instance.seen_at.elapsed() >= ttl)
+            .map(|(origin, _)| *origin)
+            .collect();
+
+        expired.into_iter()
+            .filter_map(|origin| remote.remove(&origin))
+            .flat_map(|instance| instance.statuses.into_keys())
+            .collect()
+    }
+
+    /// Applies a presence update published by another instance. The instance that published it
+    /// announces the change, so here it only becomes the last announced presence.
+    fn update_remote(&self, origin: u64, update: impl FnOnce(&mut HashMap<i32, PresenceStatus>) -> Vec<i32>) {
+        let changed = {
+            let mut remote = self.remote.lock().unwrap();
+            let instance = remote.entry(origin).or_insert_with(|| RemoteInstance {
+                statuses: HashMap::new(),
+                seen_at: Instant::now(),
+            });
+            instance.seen_at = Instant::now();
+            update(&mut instance.statuses)
+        };
+
+        for user_id in changed {
+            let status = self.presence(user_id);
+            set_status(&mut self.statuses.lock().unwrap(), user_id, status);
+        }
+    }
+
+    /// Starts or prolongs a typing indicator. Returns `true` if the user was not typing yet.
+    pub fn start_typing(&self, chat_id: i32, user_id: i32, recipients: Vec<i32>) -> bool {
+        let expires_at = Instant::now() + Duration::from_secs(TYPING_TIMEOUT_SECONDS);
+
+        self.typing
+            .lock()
+            .unwrap()
+            .insert((chat_id, user_id), Typing { expires_at, recipients })
+            .is_none()
+    }
+
+    /// Clears a typing indicator and returns who has to be told about it.
+    pub fn stop_typing(&self, chat_id: i32, user_id: i32) -> Option<Vec<i32>> {
+        self.typing
+            .lock()
+            .unwrap()
+            .remove(&(chat_id, user_id))
+            .map(|typing| typing.recipients)
+    }
+
+    /// Clears every typing indicator of the user, e.g. once their last socket closed.
+    pub fn stop_all_typing(&self, user_id: i32) -> Vec<ExpiredTyping> {
+        let mut typing = self.typing.lock().unwrap();
+        let keys: Vec<(i32, i32)> = typing.keys().filter(|(_, user)| *user == user_id).copied().collect();
+
+        keys.into_iter()
+            .filter_map(|key| typing.remove(&key).map(|entry| ExpiredTyping {
+                chat_id: key.0,
+                user_id: key.1,
+                recipients: entry.recipients,
+            }))
+            .collect()
+    }
+
+    pub fn expire_typing(&self) -> Vec<ExpiredTyping> {
+        let now = Instant::now();
+        let mut typing = self.typing.lock().unwrap();
+        let keys: Vec<(i32, i32)> = typing
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| *key)
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| typing.remove(&key).map(|entry| ExpiredTyping {
+                chat_id: key.0,
+                user_id: key.1,
+                recipients: entry.recipients,
+            }))
+            .collect()
+    }
+}
+
+/// Records the status, leaving offline users out. Returns `true` if it changed.
+fn set_status(statuses: &mut HashMap<i32, PresenceStatus>, user_id: i32, status: PresenceStatus) -> bool {
+    let previous = if status == PresenceStatus::Offline {
+        statuses.remove(&user_id)
+    } else {
+        statuses.insert(user_id, status)
+    };
+
+    previous.unwrap_or(PresenceStatus::Offline) != status
+}
+
+fn most_present(a: PresenceStatus, b: PresenceStatus) -> PresenceStatus {
+    match (a, b) {
+        (PresenceStatus::Online, _) | (_, PresenceStatus::Online) => PresenceStatus::Online,
+        (PresenceStatus::Away, _) | (_, PresenceStatus::Away) => PresenceStatus::Away,
+        _ => PresenceStatus::Offline,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audience() -> ChatAudience {
+        ChatAudience { members: vec![1, 2, 3], blocks: HashSet::from([(2, 1)]) }
+    }
+
+    #[test]
+    fn chat_recipients_leave_out_members_who_blocked_the_sender() {
+        let hub = Hub::new();
+        hub.remember_chat(10, audience(), hub.chats_version());
+
+        assert_eq!(hub.chat_recipients(10, 1), Some(vec![1, 3]));
+        assert_eq!(hub.chat_recipients(10, 2), Some(vec![1, 2, 3]));
+        assert_eq!(hub.chat_recipients(11, 1), None);
+    }
+
+    #[test]
+    fn forgotten_chats_have_to_be_loaded_again() {
+        let hub = Hub::new();
+        hub.remember_chat(10, audience(), hub.chats_version());
+        hub.remember_chat(11, ChatAudience { members: vec![4, 5], blocks: HashSet::new() }, hub.chats_version());
+
+        hub.forget_chat(10);
+        assert_eq!(hub.chat_recipients(10, 1), None);
+
+        hub.forget_member_chats(5);
+        assert_eq!(hub.chat_recipients(11, 4), None);
+    }
+
+    #[test]
+    fn audience_loaded_before_a_change_is_not_kept() {
+        let hub = Hub::new();
+        let version = hub.chats_version();
+
+        hub.forget_chat(10);
+        hub.remember_chat(10, audience(), version);
+
+        assert_eq!(hub.chat_recipients(10, 1), None);
+    }
+
+    #[test]
+    fn presence_counts_sockets_on_other_instances() {
+        let hub = Hub::new();
+        assert_eq!(hub.presence(1), PresenceStatus::Offline);
+
+        hub.deliver(7, &Delivery::Presence { user_id: 1, status: PresenceStatus::Away });
+        assert_eq!(hub.presence(1), PresenceStatus::Away);
+        assert!(hub.is_connected(1));
+        assert!(!hub.has_local_sockets(1));
+
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        hub.register(1, sender);
+        assert_eq!(hub.presence(1), PresenceStatus::Online);
+    }
+
+    #[test]
+    fn presence_snapshot_replaces_what_the_instance_published_before() {
+        let hub = Hub::new();
+        hub.deliver(7, &Delivery::Presence { user_id: 1, status: PresenceStatus::Online });
+        hub.deliver(7, &Delivery::PresenceSnapshot { statuses: vec![(2, PresenceStatus::Online)] });
+
+        assert_eq!(hub.presence(1), PresenceStatus::Offline);
+        assert_eq!(hub.presence(2), PresenceStatus::Online);
+        assert!(hub.expire_remote_presence().is_empty());
+    }
+
+    #[test]
+    fn local_presence_changes_are_published() {
+        let hub = Hub::new();
+        let (fan_out, mut published) = mpsc::unbounded_channel();
+        hub.set_fan_out(fan_out);
+
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let connection_id = hub.register(1, sender);
+        assert_eq!(hub.refresh_presence(1), Some(PresenceStatus::Online));
+        assert_eq!(published.try_recv().ok(), Some(Delivery::Presence { user_id: 1, status: PresenceStatus::Online }));
+
+        hub.unregister(connection_id);
+        assert_eq!(hub.refresh_presence(1), Some(PresenceStatus::Offline));
+        assert_eq!(published.try_recv().ok(), Some(Delivery::Presence { user_id: 1, status: PresenceStatus::Offline }));
+    }
+}
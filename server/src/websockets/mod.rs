@@ -1 +1,5 @@
-pub mod server;
\ No newline at end of file
+pub mod asyncapi;
+pub mod events;
+pub mod hub;
+pub mod server;
+pub mod fan_out;
@@ -1,17 +1,39 @@
 use sqlx::PgPool;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
 use futures_util::{StreamExt, SinkExt};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
-use tokio_tungstenite::accept_async;
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use base64::Engine;
 use std::fs::File;
 use std::io::{Write};
-
-
-type Connections = Arc<Mutex<Vec<mpsc::UnboundedSender<Message>>>>;
+use std::path::Path;
+use std::sync::LazyLock;
+use regex::Regex;
+use crate::config::{Config, UploadConfig};
+use crate::constants::common::PRESENCE_SWEEP_INTERVAL_SECONDS;
+use crate::dtos::user::PresenceStatus;
+use crate::guards::auth::authenticate;
+use crate::lifecycle::Lifecycle;
+use crate::metrics::METRICS;
+use crate::services::blocks::blocked_ids;
+use crate::services::chats::{chat_partner_ids, chat_recipient_ids, is_chat_member, return_request_message, take_request_message, RequestMessage};
+use crate::services::message_writer::{MessageWriter, NewMessage};
+use crate::services::messages::{fetch_reply_preview, is_thread_root};
+use crate::services::permissions::{member_access, ChatPermission};
+use crate::services::reactions::{set_reaction, ReactionError};
+use crate::services::receipts::{mark_delivered, mark_read};
+use crate::services::users::fetch_user_summary;
+use crate::websockets::events::{ClientEvent, ServerEvent};
+use crate::telemetry::Redacted;
+use crate::websockets::hub::Hub;
+use tracing::field::Empty;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
 
 use serde::de::{self, Deserializer, Unexpected};
 
@@ -30,8 +52,9 @@ where
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct NewMessageRequest {
+/// A chat message sent by a client. The server broadcasts it back with `id`, `reply_to` and `sender` added.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct NewMessageRequest {
     #[serde(deserialize_with = "deserialize_str_or_number")]
     chat_id: i32,  // Число, яке може бути передано як рядок чи число
     #[serde(deserialize_with = "deserialize_str_or_number")]
@@ -40,115 +63,583 @@ struct NewMessageRequest {
     file_data: Option<String>,
     file_path: Option<String>,
     message_type: String,      // Type of message (e.g., "file" or "text")
+    reply_to_message_id: Option<i32>,
+    thread_root_id: Option<i32>,   // Повідомлення належить гілці цього кореневого повідомлення
+}
+
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IncomingFrame {
+    Event(ClientEvent),
+    Message(NewMessageRequest),
 }
 
+/// Accepts WebSocket connections until shutdown starts. The listener is bound by the caller,
+/// so a taken port stops the server at startup instead of leaving it half running.
+pub async fn websocket_server(
+    listener: TcpListener,
+    pool: PgPool,
+    hub: Arc<Hub>,
+    config: Arc<Config>,
+    lifecycle: Arc<Lifecycle>,
+    writer: MessageWriter,
+) {
+    info!(addr = %config.websocket.bind_address, "WebSocket server running");
 
-pub async fn websocket_server(pool: PgPool) {
-    let addr = "127.0.0.1:9000";
-    let listener = TcpListener::bind(addr).await.expect("Failed to bind WebSocket server");
-    let connections = Arc::new(Mutex::new(Vec::new()));
+    tokio::spawn(sweep_presence(hub.clone(), pool.clone()));
+    lifecycle.set_websocket_listening(true);
 
-    println!("WebSocket server running on ws://{}", addr);
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = lifecycle.shutdown_started() => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!(error = %e, "Failed to accept WebSocket connection");
+                    break;
+                }
+            },
+        };
 
-    while let Ok((stream, _)) = listener.accept().await {
-        let connections = connections.clone();
+        let hub = hub.clone();
         let pool = pool.clone();
+        let config = config.clone();
+        let writer = writer.clone();
+        let lifecycle_for_connection = lifecycle.clone();
+
+        let span = info_span!("connection", %peer, user_id = Empty, connection_id = Empty);
+
+        lifecycle.connections().spawn(async move {
+            // Браузер не може передати заголовок Authorization, тому токен приходить як ?token=
+            let mut token = None;
+            let handshake = accept_hdr_async(stream, |request: &Request, response: Response| {
+                token = token_from_query(request.uri().query());
+                Ok(response)
+            })
+                .await;
 
-        tokio::spawn(async move {
-            if let Ok(ws_stream) = accept_async(stream).await {
-                handle_connection(ws_stream, connections, pool).await;
+            if let Ok(mut ws_stream) = handshake {
+                match authenticate_token(&pool, &config, token).await {
+                    Some(user_id) => {
+                        Span::current().record("user_id", user_id);
+                        handle_connection(ws_stream, hub, pool, config, writer, &lifecycle_for_connection, user_id).await
+                    }
+                    None => {
+                        debug!("Rejected connection with a missing or invalid token");
+                        let _ = ws_stream.close(None).await;
+                    }
+                }
             }
-        });
+        }.instrument(span));
+    }
+
+    lifecycle.set_websocket_listening(false);
+    info!("WebSocket server stopped accepting connections");
+}
+
+fn token_from_query(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "token")
+        .map(|(_, value)| value.to_string())
+}
+
+async fn authenticate_token(pool: &PgPool, config: &Config, token: Option<String>) -> Option<i32> {
+    let Some(token) = token else {
+        METRICS.auth_failures.with_label_values(&["missing_token"]).inc();
+        return None;
+    };
+
+    let user_id = authenticate(pool, &config.auth, &token).await;
+
+    if user_id.is_none() {
+        METRICS.auth_failures.with_label_values(&["invalid_token"]).inc();
     }
+
+    user_id
 }
 
 async fn handle_connection(
     ws_stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
-    connections: Connections,
+    hub: Arc<Hub>,
     pool: PgPool,
+    config: Arc<Config>,
+    writer: MessageWriter,
+    lifecycle: &Lifecycle,
+    user_id: i32,
 ) {
     let (mut write, mut read) = ws_stream.split();
 
     let (tx, mut rx) = mpsc::unbounded_channel();
-    connections.lock().unwrap().push(tx);
+    let connection_id = hub.register(user_id, tx);
+    Span::current().record("connection_id", connection_id);
+    info!("Connection opened");
+    announce_presence_change(&hub, &pool, user_id).await;
 
-    tokio::spawn(async move {
+    // Читач теж відстежується, щоб під час зупинки дочекатися збереження вже отриманих повідомлень
+    lifecycle.connections().spawn(async move {
         while let Some(Ok(msg)) = read.next().await {
             if msg.is_text() {
+                hub.touch(connection_id);
+
                 let msg_text = msg.to_text().unwrap();
-                println!("Received message: {}", msg_text); // Логування отриманого повідомлення
-
-                if let Ok(request) = serde_json::from_str::<NewMessageRequest>(msg_text) {
-                    println!("Parsed message: {:?}", request); // Логування розпарсеного повідомлення
-
-                    if request.message_type == "file" {
-                        if let Some(ref file_data) = request.file_data {
-                            // Використовуємо переданий шлях до файлу
-                            if let Some(ref file_path) = request.file_path {
-                                if let Err(e) = save_file(&file_data, &file_path).await {
-                                    eprintln!("Failed to save file: {}", e);
-                                }
-                            } else {
-                                eprintln!("File path not provided");
-                            }
-                        }
-                    }
+                debug!(bytes = msg_text.len(), "Received frame");
 
-                    if let Err(e) = save_message_to_db(&pool, &request).await {
-                        eprintln!("Failed to save message: {}", e);
+                match serde_json::from_str::<IncomingFrame>(msg_text) {
+                    Ok(IncomingFrame::Event(event)) => {
+                        handle_event(&hub, &pool, connection_id, user_id, event).await;
                     }
+                    Ok(IncomingFrame::Message(request)) => {
+                        debug!(
+                            chat_id = request.chat_id,
+                            message_type = %request.message_type,
+                            content = %Redacted(&request.content),
+                            "Received chat message"
+                        );
 
-                    // Формуємо JSON для відповіді
-                    let response = serde_json::json!(request);
-                    let response_text = response.to_string();
-
-                    // Відправляємо повідомлення всім підключеним користувачам
-                    for sender in connections.lock().unwrap().iter() {
-                        let _ = sender.send(Message::Text(response_text.clone()));
+                        handle_new_message(&hub, &pool, &writer, &config.uploads, connection_id, user_id, request).await;
                     }
-                } else {
-                    eprintln!("Failed to parse message");
+                    Err(e) => warn!(error = %e, "Failed to parse frame"),
                 }
             }
         }
-    });
+
+        hub.unregister(connection_id);
+
+        if !hub.has_local_sockets(user_id) {
+            for typing in hub.stop_all_typing(user_id) {
+                let event = ServerEvent::TypingStopped { chat_id: typing.chat_id, user_id };
+                hub.send_to_users(&typing.recipients, &event.to_message());
+            }
+        }
+
+        // Користувач міг лишитися на зв'язку через інший інстанс
+        if hub.presence(user_id) == PresenceStatus::Offline {
+            if let Err(e) = sqlx::query!("UPDATE users SET last_seen_at = NOW() WHERE id = $1", user_id)
+                .execute(&pool)
+                .await
+            {
+                error!(error = %e, "Failed to update last seen");
+            }
+        }
+
+        announce_presence_change(&hub, &pool, user_id).await;
+        info!("Connection closed");
+    }.instrument(Span::current()));
 
     while let Some(msg) = rx.recv().await {
+        METRICS.outbound_queue_depth.dec();
         let _ = write.send(msg).await;
     }
 }
 
-async fn save_message_to_db(
+fn reject(hub: &Hub, connection_id: usize, message: &str) {
+    warn!(reason = message, "Rejected client request");
+    let event = ServerEvent::Error { message: message.to_string() };
+    hub.send_to_connection(connection_id, &event.to_message());
+}
+
+async fn handle_new_message(
+    hub: &Arc<Hub>,
     pool: &PgPool,
-    request: &NewMessageRequest,
-) -> Result<(), sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        INSERT INTO messages (chat_id, user_id, content, created_at, file_path, message_type)
-        VALUES ($1, $2, $3, NOW(), $4, $5)
-        "#,
-        request.chat_id,
-        request.user_id,
-        request.content,
-        request.file_path.as_deref(), // Якщо є шлях до файлу, зберігаємо його
-        request.message_type
+    writer: &MessageWriter,
+    uploads: &UploadConfig,
+    connection_id: usize,
+    user_id: i32,
+    mut request: NewMessageRequest,
+) {
+    if request.user_id != user_id {
+        return reject(hub, connection_id, "Message author does not match the authenticated user");
+    }
+
+    let access = match member_access(pool, request.chat_id, user_id).await {
+        Ok(Some(access)) => access,
+        Ok(None) => return reject(hub, connection_id, "You are not a member of this chat"),
+        Err(e) => {
+            error!(error = %e, "Failed to check chat membership");
+            return reject(hub, connection_id, "Failed to send message");
+        }
+    };
+
+    if !access.can(ChatPermission::Post) {
+        return reject(hub, connection_id, "You can't post in this chat");
+    }
+
+    if request.message_type == "file" && !access.can(ChatPermission::SendFiles) {
+        return reject(hub, connection_id, "You can't send files in this chat");
+    }
+
+    // Base64 кодує кожні 3 байти чотирма символами
+    if request.file_data.as_ref().is_some_and(|file_data| file_data.len() as u64 / 4 * 3 > uploads.max_file_bytes) {
+        return reject(hub, connection_id, "File is too large");
+    }
+
+    let reply_to = match request.reply_to_message_id {
+        Some(reply_to_message_id) => match fetch_reply_preview(pool, request.chat_id, reply_to_message_id).await {
+            Ok(Some(preview)) => Some(preview),
+            Ok(None) => return reject(hub, connection_id, "Replied message not found in this chat"),
+            Err(e) => {
+                error!(error = %e, "Failed to fetch replied message");
+                return reject(hub, connection_id, "Failed to send message");
+            }
+        },
+        None => None,
+    };
+
+    if let Some(thread_root_id) = request.thread_root_id {
+        match is_thread_root(pool, request.chat_id, thread_root_id).await {
+            Ok(true) => {}
+            Ok(false) => return reject(hub, connection_id, "Thread root not found in this chat"),
+            Err(e) => {
+                error!(error = %e, "Failed to fetch thread root");
+                return reject(hub, connection_id, "Failed to send message");
+            }
+        }
+    }
+
+    // Надіслане повідомлення завершує індикатор набору
+    if let Some(recipients) = hub.stop_typing(request.chat_id, user_id) {
+        let event = ServerEvent::TypingStopped { chat_id: request.chat_id, user_id };
+        hub.send_to_users(&recipients, &event.to_message());
+    }
+
+    // Користувачі, які заблокували автора, повідомлення не отримують
+    let recipients = match chat_recipient_ids(pool, hub, request.chat_id, user_id).await {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            error!(error = %e, "Failed to fetch chat members");
+            return reject(hub, connection_id, "Failed to send message");
+        }
+    };
+
+    // Поки запит на чат не прийнято, ініціатор може надіслати лише одне повідомлення
+    let request_message = match take_request_message(pool, request.chat_id, user_id).await {
+        Ok(RequestMessage::AlreadySent) => {
+            return reject(hub, connection_id, "Wait until your chat request is accepted to send more messages");
+        }
+        Ok(request_message) => request_message,
+        Err(e) => {
+            error!(error = %e, "Failed to check chat request");
+            return reject(hub, connection_id, "Failed to send message");
+        }
+    };
+
+    // Файл, який записав сервер; лише такий можна видалити разом із повідомленням.
+    // Записується він лише тоді, коли повідомлення дозволено надіслати
+    let mut file_uploaded = false;
+
+    if request.message_type == "file" {
+        if let Some(ref file_data) = request.file_data {
+            match store_file(pool, uploads, request.chat_id, request.file_path.as_deref(), file_data).await {
+                Ok(file_path) => {
+                    request.file_path = Some(file_path);
+                    file_uploaded = true;
+                }
+                Err(reason) => {
+                    give_back_request_message(pool, request.chat_id, user_id, request_message).await;
+                    return reject(hub, connection_id, reason);
+                }
+            }
+        } else {
+            warn!(chat_id = request.chat_id, "File message without file data");
+        }
+    }
+
+    // Формуємо JSON для відповіді заздалегідь, щоб розіслати його одразу після збереження
+    let mut response = serde_json::json!(request);
+    response["reply_to"] = serde_json::json!(reply_to);
+    response["sender"] = serde_json::json!(fetch_user_summary(pool, user_id).await.ok().flatten());
+
+    let chat_id = request.chat_id;
+    let thread_root_id = request.thread_root_id;
+    let file_path = request.file_path;
+    let message = NewMessage {
+        chat_id,
+        user_id,
+        content: request.content,
+        file_path: file_path.clone(),
+        file_uploaded,
+        message_type: request.message_type,
+        reply_to_message_id: request.reply_to_message_id,
+        thread_root_id,
+    };
+
+    // Розсилка виконується в записувачі після коміту, у порядку повідомлень чату
+    let broadcast = {
+        let hub = hub.clone();
+        let recipients = recipients.clone();
+
+        Box::new(move |message_id: i32| {
+            response["id"] = serde_json::json!(message_id);
+
+            match thread_root_id {
+                None => hub.send_to_users(&recipients, &Message::Text(response.to_string())),
+                Some(thread_root_id) => {
+                    // Відповідь у гілці бачать лише ті, хто її відкрив; решта отримує оновлений лічильник
+                    let event = ServerEvent::ThreadReply { chat_id, thread_root_id, message: response };
+                    hub.send_to_thread(thread_root_id, &recipients, &event.to_message());
+                }
+            }
+        })
+    };
+
+    let message_id = match writer.save(message, broadcast).await {
+        Ok(message_id) => message_id,
+        Err(e) => {
+            error!(error = %e, "Failed to save message");
+
+            // Файл без повідомлення нікому не потрібен
+            if let (true, Some(file_path)) = (file_uploaded, &file_path) {
+                if let Err(e) = tokio::fs::remove_file(uploads.path_of(file_path)).await {
+                    warn!(error = %e, file_path = %Redacted(file_path), "Failed to remove file");
+                }
+            }
+
+            give_back_request_message(pool, chat_id, user_id, request_message).await;
+            return reject(hub, connection_id, "Failed to send message");
+        }
+    };
+
+    if let Some(thread_root_id) = thread_root_id {
+        announce_thread_update(hub, pool, chat_id, thread_root_id, &recipients).await;
+    }
+
+    mark_delivered_to_connected(hub, pool, chat_id, &recipients, user_id, message_id).await;
+}
+
+/// Tells the recipients of a reply about the thread's new reply count.
+async fn announce_thread_update(hub: &Hub, pool: &PgPool, chat_id: i32, thread_root_id: i32, recipients: &[i32]) {
+    let root = sqlx::query!(
+        "SELECT reply_count, last_reply_at FROM messages WHERE id = $1",
+        thread_root_id
     )
-        .execute(pool)
+        .fetch_one(pool)
         .await;
 
-    match result {
-        Ok(_) => {
-            println!("Message saved successfully");
-            Ok(())
+    match root {
+        Ok(root) => {
+            let event = ServerEvent::ThreadUpdated {
+                chat_id,
+                message_id: thread_root_id,
+                reply_count: root.reply_count,
+                last_reply_at: root.last_reply_at,
+            };
+            hub.send_to_users(recipients, &event.to_message());
+        }
+        Err(e) => error!(error = %e, "Failed to announce thread update"),
+    }
+}
+
+async fn handle_event(hub: &Hub, pool: &PgPool, connection_id: usize, user_id: i32, event: ClientEvent) {
+    match event {
+        ClientEvent::TypingStarted { chat_id } => {
+            let recipients = match chat_recipient_ids(pool, hub, chat_id, user_id).await {
+                Ok(recipients) => recipients,
+                Err(e) => {
+                    error!(error = %e, "Failed to fetch chat members");
+                    return;
+                }
+            };
+
+            if !recipients.contains(&user_id) {
+                return;
+            }
+
+            let recipients: Vec<i32> = recipients.into_iter().filter(|member| *member != user_id).collect();
+
+            if hub.start_typing(chat_id, user_id, recipients.clone()) {
+                let event = ServerEvent::TypingStarted { chat_id, user_id };
+                hub.send_to_users(&recipients, &event.to_message());
+            }
+        }
+        ClientEvent::TypingStopped { chat_id } => {
+            if let Some(recipients) = hub.stop_typing(chat_id, user_id) {
+                let event = ServerEvent::TypingStopped { chat_id, user_id };
+                hub.send_to_users(&recipients, &event.to_message());
+            }
+        }
+        ClientEvent::SetPresence { status } => {
+            hub.set_away(connection_id, status != PresenceStatus::Online);
+            announce_presence_change(hub, pool, user_id).await;
+        }
+        ClientEvent::MarkRead { chat_id, message_id } => {
+            if let Err(e) = mark_read(pool, hub, chat_id, user_id, message_id).await {
+                error!(error = %e, "Failed to update read marker");
+            }
+        }
+        ClientEvent::SubscribeThread { chat_id, message_id } => {
+            let allowed = match is_chat_member(pool, chat_id, user_id).await {
+                Ok(true) => is_thread_root(pool, chat_id, message_id).await.unwrap_or(false),
+                _ => false,
+            };
+
+            if allowed {
+                hub.subscribe_thread(connection_id, message_id);
+            } else {
+                reject(hub, connection_id, "Thread not found");
+            }
+        }
+        ClientEvent::UnsubscribeThread { message_id } => {
+            hub.unsubscribe_thread(connection_id, message_id);
+        }
+        ClientEvent::AddReaction { chat_id, message_id, emoji } => {
+            react(hub, pool, connection_id, user_id, chat_id, message_id, &emoji, true).await;
+        }
+        ClientEvent::RemoveReaction { chat_id, message_id, emoji } => {
+            react(hub, pool, connection_id, user_id, chat_id, message_id, &emoji, false).await;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn react(hub: &Hub, pool: &PgPool, connection_id: usize, user_id: i32, chat_id: i32, message_id: i32, emoji: &str, reacted: bool) {
+    match is_chat_member(pool, chat_id, user_id).await {
+        Ok(true) => {}
+        _ => return reject(hub, connection_id, "You are not a member of this chat"),
+    }
+
+    match set_reaction(pool, hub, chat_id, message_id, user_id, emoji, reacted).await {
+        Ok(_) => {}
+        Err(ReactionError::InvalidEmoji) => reject(hub, connection_id, "Reaction must be a single emoji"),
+        Err(ReactionError::MessageNotFound) => reject(hub, connection_id, "Message not found"),
+        Err(ReactionError::Database(e)) => {
+            error!(error = %e, "Failed to update reaction");
+            reject(hub, connection_id, "Failed to update reaction");
+        }
+    }
+}
+
+/// Recipients with an open socket have just received the message, so their delivery cursor moves.
+async fn mark_delivered_to_connected(hub: &Hub, pool: &PgPool, chat_id: i32, recipients: &[i32], author_id: i32, message_id: i32) {
+    for member in recipients.iter().copied().filter(|member| *member != author_id && hub.is_connected(*member)) {
+        if let Err(e) = mark_delivered(pool, hub, chat_id, member, message_id).await {
+            error!(error = %e, "Failed to update delivery cursor");
         }
+    }
+}
+
+/// Tells everyone who shares a chat with the user about a presence change,
+/// unless the user hides their presence or blocked them.
+pub async fn announce_presence_change(hub: &Hub, pool: &PgPool, user_id: i32) {
+    let status = match hub.refresh_presence(user_id) {
+        Some(status) => status,
+        None => return,
+    };
+
+    let user = match sqlx::query!(
+        "SELECT hide_presence, last_seen_at FROM users WHERE id = $1",
+        user_id
+    )
+        .fetch_one(pool)
+        .await
+    {
+        Ok(user) => user,
         Err(e) => {
-            eprintln!("Failed to save message: {}", e);
-            Err(e)
+            error!(error = %e, "Failed to fetch presence settings");
+            return;
+        }
+    };
+
+    if user.hide_presence {
+        return;
+    }
+
+    let partners = match chat_partner_ids(pool, user_id).await {
+        Ok(partners) => partners,
+        Err(e) => return error!(error = %e, "Failed to fetch chat partners"),
+    };
+
+    // Заблоковані користувачі не бачать присутності
+    let blocked = match blocked_ids(pool, user_id, &partners).await {
+        Ok(blocked) => blocked,
+        Err(e) => return error!(error = %e, "Failed to fetch blocked users"),
+    };
+
+    let recipients: Vec<i32> = partners.into_iter().filter(|partner| !blocked.contains(partner)).collect();
+    let event = ServerEvent::PresenceChanged { user_id, status, last_seen: user.last_seen_at };
+    hub.send_to_users(&recipients, &event.to_message());
+}
+
+/// Expires stale typing indicators and notices sockets that went idle and instances that went away.
+async fn sweep_presence(hub: Arc<Hub>, pool: PgPool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(PRESENCE_SWEEP_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        for typing in hub.expire_typing() {
+            let event = ServerEvent::TypingStopped { chat_id: typing.chat_id, user_id: typing.user_id };
+            hub.send_to_users(&typing.recipients, &event.to_message());
+        }
+
+        // Користувачі інстансу, що перестав відповідати, більше не на зв'язку
+        for user_id in hub.connected_user_ids().into_iter().chain(hub.expire_remote_presence()) {
+            announce_presence_change(&hub, &pool, user_id).await;
         }
     }
 }
 
-async fn save_file(file_data: &str, file_path: &str) -> Result<(), std::io::Error> {
+/// Returns the message a requester used up when their message wasn't sent after all.
+async fn give_back_request_message(pool: &PgPool, chat_id: i32, user_id: i32, request_message: RequestMessage) {
+    if request_message == RequestMessage::Taken {
+        if let Err(e) = return_request_message(pool, chat_id, user_id).await {
+            error!(error = %e, "Failed to return chat request message");
+        }
+    }
+}
+
+/// Writes a file sent with a message under a name generated by the server and returns its path,
+/// or the reason to give the client.
+async fn store_file(
+    pool: &PgPool,
+    uploads: &UploadConfig,
+    chat_id: i32,
+    requested: Option<&str>,
+    file_data: &str,
+) -> Result<String, &'static str> {
+    // Ім'я файлу генерує сервер, від клієнта беремо лише оригінальну назву
+    let file_path = stored_file_path(pool, chat_id, requested).await.map_err(|e| {
+        error!(error = %e, "Failed to generate file name");
+        "Failed to send message"
+    })?;
+
+    if let Err(e) = save_file(file_data, &uploads.path_of(&file_path)).await {
+        error!(error = %e, file_path = %Redacted(&file_path), "Failed to save file");
+        return Err("Failed to save file");
+    }
+
+    Ok(file_path)
+}
+
+/// Where a file sent with a message is stored: `uploads/chat_<chat id>_<uuid>_<name>`, keeping only
+/// the original name from the path the client asked for.
+async fn stored_file_path(pool: &PgPool, chat_id: i32, requested: Option<&str>) -> Result<String, sqlx::Error> {
+    let uuid = sqlx::query_scalar!(r#"SELECT gen_random_uuid()::TEXT AS "uuid!""#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(format!("uploads/chat_{}_{}_{}", chat_id, uuid, original_file_name(requested.unwrap_or_default())))
+}
+
+/// The name a client gave its file, without the directory and the `chat_<id>_<uuid>_` prefix
+/// the web client adds, reduced to characters that are safe in a file name.
+fn original_file_name(requested: &str) -> String {
+    static CLIENT_PREFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^chat_\d+_[0-9a-fA-F-]{36}_").unwrap());
+
+    let file_name = Path::new(requested).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let name: String = CLIENT_PREFIX
+        .replace(file_name, "")
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ' '))
+        .take(100)
+        .collect();
+
+    if name.trim_matches('.').is_empty() { "file".to_string() } else { name }
+}
+
+async fn save_file(file_data: &str, file_path: &Path) -> Result<(), std::io::Error> {
     // Видаляємо префікс 'data:*/*;base64,' якщо він є
     let file_content = file_data.split(',').nth(1).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file data"))?;
 
@@ -156,11 +647,37 @@ async fn save_file(file_data: &str, file_path: &str) -> Result<(), std::io::Erro
     let decoded_data = base64::prelude::BASE64_STANDARD.decode(file_content)
         .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to decode base64"))?;
 
-    // Створюємо файл та записуємо дані за вказаним шляхом
-    let mut file = File::create(file_path)?;
+    // Створюємо новий файл; наявний не перезаписуємо
+    let mut file = File::create_new(file_path)?;
     file.write_all(&decoded_data)?;
+    METRICS.upload_bytes.with_label_values(&["file"]).inc_by(decoded_data.len() as u64);
 
-    println!("File saved: {}", file_path);
+    debug!(file_path = %Redacted(&file_path.to_string_lossy()), bytes = decoded_data.len(), "File saved");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_is_read_from_the_query() {
+        assert_eq!(token_from_query(Some("token=abc.def")), Some("abc.def".to_string()));
+        assert_eq!(token_from_query(Some("client=web&token=abc&v=2")), Some("abc".to_string()));
+    }
+
+    #[test]
+    fn first_token_wins() {
+        assert_eq!(token_from_query(Some("token=first&token=second")), Some("first".to_string()));
+    }
+
+    #[test]
+    fn missing_token_is_none() {
+        assert_eq!(token_from_query(None), None);
+        assert_eq!(token_from_query(Some("")), None);
+        assert_eq!(token_from_query(Some("client=web")), None);
+        assert_eq!(token_from_query(Some("token")), None);
+        assert_eq!(token_from_query(Some("tokens=abc")), None);
+    }
+}